@@ -1,4 +1,5 @@
-use clap::Args;
+use clap::{Args, ValueEnum};
+use size::Size;
 use std::path::PathBuf;
 
 #[derive(Args)]
@@ -18,4 +19,109 @@ pub struct BuildArgs {
     /// Path to Acode manifest
     #[arg(long)]
     pub manifest: Option<PathBuf>,
+
+    /// Path to config file [default: naql.toml]
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Directory of templates overlaid on the embedded runtime templates
+    #[arg(long)]
+    pub templates: Option<PathBuf>,
+
+    /// Directory of .vsix files or extension directories to build the members
+    /// of an extension pack from, each is written to `<id>.zip`
+    #[arg(long)]
+    pub pack_dir: Option<PathBuf>,
+
+    /// Compression of the plugin zip, already compressed formats are always
+    /// stored
+    #[arg(long, value_enum, default_value_t = Compression::Deflate)]
+    pub compression: Compression,
+
+    /// Compression level, 1-9 (10-264 use zopfli) for deflate, -7-22 for zstd
+    #[arg(long, allow_hyphen_values = true)]
+    pub compression_level: Option<i64>,
+
+    /// Largest the plugin zip may be, e.g. `500kb` or `2mb`, the build fails
+    /// above it
+    #[arg(long)]
+    pub max_size: Option<Size>,
+
+    #[command(flatten)]
+    pub icons: IconArgs,
+
+    /// Bundler used for the generated plugin code
+    #[arg(long, value_enum, default_value_t = Bundler::Embedded)]
+    pub bundler: Bundler,
+
+    /// Build profile, `dev` skips minification and emits source maps
+    #[arg(long, value_enum, default_value_t = Profile::Release)]
+    pub profile: Profile,
+
+    /// How source maps are emitted in the `dev` profile
+    #[arg(long, value_enum, default_value_t = SourceMap::External)]
+    pub sourcemap: SourceMap,
+}
+
+/// How the assets of icon themes are bundled
+#[derive(Args, Clone, Copy)]
+pub struct IconArgs {
+    /// Minify SVG icons, dropping metadata and editor markup and rounding
+    /// numbers
+    #[arg(long)]
+    pub minify_svg: bool,
+
+    /// Inline SVG and PNG icons up to this size, e.g. `2kb`, in the theme CSS
+    /// as data URIs. The theme JSON still points at the bundled files
+    #[arg(long)]
+    pub inline_icons: Option<Size>,
+
+    /// Pack the SVG icons of each theme into an SVG sprite and its PNG icons
+    /// into an atlas, referenced instead of the single files, which are left
+    /// out of the plugin
+    #[arg(long)]
+    pub sprite: bool,
+
+    /// Keep icon definitions no file, folder or language is mapped to
+    #[arg(long)]
+    pub keep_unused_icons: bool,
+}
+
+#[derive(Args)]
+pub struct ValidateArgs {
+    /// Path to plugin .zip or directory
+    #[arg(default_value = ".")]
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Bundler {
+    /// Bundle and minify in-process
+    Embedded,
+    /// Use `esbuild` from PATH or node_modules/.bin
+    Esbuild,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Profile {
+    /// Unminified output with source maps
+    Dev,
+    /// Minified output without source maps
+    Release,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SourceMap {
+    /// Append the source map to `main.js` as a data url
+    Inline,
+    /// Write the source map to `main.js.map`
+    External,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    Deflate,
+    /// Not supported by Acode, for local testing
+    Zstd,
+    None,
 }
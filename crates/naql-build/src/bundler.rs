@@ -0,0 +1,450 @@
+use anyhow::{Result, anyhow, bail};
+use naql_shared::{join, own};
+use oxc::allocator::Allocator;
+use oxc::ast::ast::{
+    Declaration, ExportDefaultDeclarationKind, Expression, ImportDeclarationSpecifier,
+    ImportExpression, ModuleDeclaration,
+};
+use oxc::ast_visit::{Visit, walk};
+use oxc::codegen::{Codegen, CodegenOptions};
+use oxc::minifier::{Minifier, MinifierOptions};
+use oxc::parser::Parser;
+use oxc::span::{GetSpan, SourceType, Span};
+use oxc_sourcemap::SourceMapBuilder;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+use walkdir::WalkDir;
+
+const RUNTIME: &str = r#"var __cache = {};
+var __export = (target, all) => {
+  for (var name in all) Object.defineProperty(target, name, { get: all[name], enumerable: true });
+};
+var __require = (id) => {
+  var exports = __cache[id];
+  if (!exports) {
+    exports = __cache[id] = {};
+    __modules[id](exports);
+  }
+  return exports;
+};
+var __import = (map, path) =>
+  Object.prototype.hasOwnProperty.call(map, path)
+    ? Promise.resolve().then(() => __require(map[path]))
+    : Promise.reject(new Error(`Could not resolve "${path}"`));
+"#;
+
+/// A minimal in-process bundler for the ES modules naql generates from the
+/// runtime templates. It understands relative static imports, named and
+/// default exports, json modules and `import()` of string or template
+/// literals. Anything else needs `--bundler esbuild`.
+pub struct Bundler {
+    root: PathBuf,
+    modules: BTreeMap<String, Module>,
+}
+
+struct Module {
+    source: String,
+    code: String,
+}
+
+pub struct Bundle {
+    pub code: String,
+    /// Line-level source map of `code` back to the modules under the root
+    pub map: String,
+}
+
+impl Bundle {
+    pub fn map_data_url(&self) -> Result<String> {
+        Ok(oxc_sourcemap::SourceMap::from_json_string(&self.map)?.to_data_url())
+    }
+}
+
+enum Specifier {
+    Literal(String),
+    Template(Vec<String>),
+    Unknown,
+}
+
+#[derive(Default)]
+struct DynamicImports {
+    found: Vec<(Span, Specifier)>,
+}
+
+impl<'a> Visit<'a> for DynamicImports {
+    fn visit_import_expression(&mut self, it: &ImportExpression<'a>) {
+        let spec = match &it.source {
+            Expression::StringLiteral(lit) => Specifier::Literal(own!(lit.value.as_str())),
+            Expression::TemplateLiteral(lit) => Specifier::Template(
+                lit.quasis
+                    .iter()
+                    .map(|q| own!(q.value.cooked.as_ref().unwrap_or(&q.value.raw).as_str()))
+                    .collect(),
+            ),
+            _ => Specifier::Unknown,
+        };
+        self.found
+            .push((Span::new(it.span.start, it.source.span().start), spec));
+        walk::walk_import_expression(self, it);
+    }
+}
+
+impl Bundler {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            modules: BTreeMap::new(),
+        }
+    }
+
+    /// Bundles `entry` (relative to the root) and everything it imports into
+    /// a single script. Module bodies keep their original line layout so the
+    /// bundle stays readable and maps back line by line.
+    pub fn bundle(mut self, entry: &str) -> Result<Bundle> {
+        let mut queue = VecDeque::from([own!(entry)]);
+
+        while let Some(id) = queue.pop_front() {
+            if self.modules.contains_key(&id) {
+                continue;
+            }
+
+            debug!("bundle {id}");
+            let source = read_to_string(join!(&self.root, &id))?;
+            let code = if id.ends_with(".json") {
+                format!(
+                    "var __json = ({source});\nObject.assign(__exports, __json);\n__exports.default = __json;"
+                )
+            } else {
+                self.transform(&id, &source, &mut queue)?
+            };
+            self.modules.insert(id, Module { source, code });
+        }
+
+        let sources = self
+            .modules
+            .keys()
+            .map(|id| format!("../src/{id}"))
+            .collect::<Vec<_>>();
+        let mut map = SourceMapBuilder::default();
+        let mut out = String::from("(() => {\nvar __modules = {\n");
+        let mut line = 2;
+        for ((id, module), source) in self.modules.iter().zip(&sources) {
+            out.push_str(&format!(
+                "{}: function (__exports) {{\n\"use strict\";\n{}\n}},\n",
+                quote(id),
+                module.code
+            ));
+            line += 2;
+
+            let src = map.add_source_and_content(source, &module.source);
+            let lines = module.code.matches('\n').count() as u32 + 1;
+            if !id.ends_with(".json") {
+                for i in 0..lines {
+                    map.add_token(line + i, 0, i, 0, Some(src), None);
+                }
+            }
+            line += lines + 1;
+        }
+        out.push_str("};\n");
+        out.push_str(RUNTIME);
+        out.push_str(&format!("__require({});\n}})();\n", quote(entry)));
+
+        Ok(Bundle {
+            code: out,
+            map: map.into_sourcemap().to_json_string(),
+        })
+    }
+
+    fn transform(&self, id: &str, source: &str, queue: &mut VecDeque<String>) -> Result<String> {
+        let allocator = Allocator::default();
+        let ret = Parser::new(&allocator, source, SourceType::mjs()).parse();
+        if let Some(error) = ret.diagnostics.errors().next() {
+            bail!("{id}: {error}");
+        }
+
+        let mut edits: Vec<(Span, String)> = vec![];
+        let mut exports: Vec<(String, String)> = vec![];
+        let require = |spec: &str, queue: &mut VecDeque<String>| -> Result<String> {
+            let resolved = self.resolve(id, spec)?;
+            queue.push_back(resolved.clone());
+            Ok(format!("__require({})", quote(&resolved)))
+        };
+
+        for (i, stmt) in ret.program.body.iter().enumerate() {
+            let Some(decl) = stmt.as_module_declaration() else {
+                continue;
+            };
+
+            match decl {
+                ModuleDeclaration::ImportDeclaration(import) => {
+                    let module = require(&import.source.value, queue)?;
+                    let mut names = vec![];
+                    let mut code = String::new();
+                    for specifier in import.specifiers.iter().flatten() {
+                        match specifier {
+                            ImportDeclarationSpecifier::ImportSpecifier(s) => names.push(format!(
+                                "{}: {}",
+                                quote(&s.imported.name()),
+                                s.local.name
+                            )),
+                            ImportDeclarationSpecifier::ImportDefaultSpecifier(s) => {
+                                names.push(format!("\"default\": {}", s.local.name))
+                            }
+                            ImportDeclarationSpecifier::ImportNamespaceSpecifier(s) => {
+                                code.push_str(&format!("var {} = {module};", s.local.name))
+                            }
+                        }
+                    }
+                    if !names.is_empty() {
+                        code.push_str(&format!("var {{ {} }} = {module};", names.join(", ")));
+                    } else if code.is_empty() {
+                        code = format!("{module};");
+                    }
+                    edits.push((import.span, code));
+                }
+                ModuleDeclaration::ExportDefaultDeclaration(export) => {
+                    let (start, local) = match &export.declaration {
+                        ExportDefaultDeclarationKind::FunctionDeclaration(f) => {
+                            (f.span.start, f.id.as_ref().map(|id| own!(id.name.as_str())))
+                        }
+                        ExportDefaultDeclarationKind::ClassDeclaration(c) => {
+                            (c.span.start, c.id.as_ref().map(|id| own!(id.name.as_str())))
+                        }
+                        kind => (kind.span().start, None),
+                    };
+                    let head = Span::new(export.span.start, start);
+                    match local {
+                        Some(local) => {
+                            edits.push((head, own!("")));
+                            exports.push((own!("default"), local));
+                        }
+                        None => {
+                            edits.push((head, own!("var __default = ")));
+                            edits.push((Span::empty(export.span.end), own!(";")));
+                            exports.push((own!("default"), own!("__default")));
+                        }
+                    }
+                }
+                ModuleDeclaration::ExportNamedDeclaration(export) => {
+                    if let Some(declaration) = &export.declaration {
+                        let names = match declaration {
+                            Declaration::VariableDeclaration(v) => v
+                                .declarations
+                                .iter()
+                                .flat_map(|d| d.id.get_binding_identifiers())
+                                .map(|id| own!(id.name.as_str()))
+                                .collect(),
+                            declaration => declaration
+                                .id()
+                                .map(|id| own!(id.name.as_str()))
+                                .into_iter()
+                                .collect::<Vec<_>>(),
+                        };
+                        edits.push((
+                            Span::new(export.span.start, declaration.span().start),
+                            own!(""),
+                        ));
+                        exports.extend(names.into_iter().map(|name| (name.clone(), name)));
+                        continue;
+                    }
+
+                    let from = match &export.source {
+                        Some(source) => {
+                            let local = format!("__reexport{i}");
+                            let module = require(&source.value, queue)?;
+                            edits.push((export.span, format!("var {local} = {module};")));
+                            Some(local)
+                        }
+                        None => {
+                            edits.push((export.span, own!("")));
+                            None
+                        }
+                    };
+                    for specifier in &export.specifiers {
+                        let local = match &from {
+                            Some(module) => format!("{module}[{}]", quote(&specifier.local.name())),
+                            None => own!(specifier.local.name().as_str()),
+                        };
+                        exports.push((own!(specifier.exported.name().as_str()), local));
+                    }
+                }
+                ModuleDeclaration::ExportAllDeclaration(export) => {
+                    bail!(
+                        "{id}: `export * from {}` is not supported by the embedded bundler, use --bundler esbuild",
+                        quote(&export.source.value)
+                    )
+                }
+                _ => {}
+            }
+        }
+
+        let mut dynamic = DynamicImports::default();
+        dynamic.visit_program(&ret.program);
+        for (head, spec) in dynamic.found {
+            let map = match spec {
+                Specifier::Literal(spec) => vec![(spec.clone(), self.resolve(id, &spec)?)],
+                Specifier::Template(quasis) => self.glob(id, &quasis)?,
+                Specifier::Unknown => bail!(
+                    "{id}: import() needs a string or template literal with the embedded bundler, use --bundler esbuild"
+                ),
+            };
+            let map = map
+                .into_iter()
+                .map(|(spec, resolved)| {
+                    let entry = format!("{}: {}", quote(&spec), quote(&resolved));
+                    queue.push_back(resolved);
+                    entry
+                })
+                .collect::<Vec<_>>();
+            edits.push((head, format!("__import({{ {} }}, ", map.join(", "))));
+        }
+
+        if !exports.is_empty() {
+            let getters = exports
+                .iter()
+                .map(|(name, local)| format!("{}: () => {local}", quote(name)))
+                .collect::<Vec<_>>();
+            edits.push((
+                Span::empty(0),
+                format!("__export(__exports, {{ {} }});", getters.join(", ")),
+            ));
+        }
+
+        edits.sort_by_key(|(span, _)| (span.start, span.end));
+        let mut out = String::with_capacity(source.len());
+        let mut last = 0;
+        for (span, text) in edits {
+            out.push_str(&source[last..span.start as usize]);
+            out.push_str(&text);
+            // keep the line layout of the original module
+            let removed = &source[span.start as usize..span.end as usize];
+            out.push_str(&"\n".repeat(removed.matches('\n').count()));
+            last = span.end as usize;
+        }
+        out.push_str(&source[last..]);
+
+        Ok(out)
+    }
+
+    /// Resolves `spec` imported from the module `from` to a module id.
+    fn resolve(&self, from: &str, spec: &str) -> Result<String> {
+        let base = normalize(from, spec).ok_or_else(|| {
+            anyhow!(
+                "{from}: cannot resolve {}, bare imports need --bundler esbuild",
+                quote(spec)
+            )
+        })?;
+
+        for candidate in [
+            base.clone(),
+            format!("{base}.js"),
+            format!("{base}.mjs"),
+            format!("{base}.json"),
+            format!("{base}/index.js"),
+        ] {
+            if join!(&self.root, &candidate).is_file() {
+                return Ok(candidate);
+            }
+        }
+
+        bail!("{from}: cannot resolve {}", quote(spec))
+    }
+
+    /// Expands a template literal specifier into every module it could name,
+    /// where each `${}` matches any run of characters except `/`.
+    fn glob(&self, from: &str, quasis: &[String]) -> Result<Vec<(String, String)>> {
+        let pattern = quasis.join("*");
+        let split = pattern[..quasis[0].len()].rfind('/').map_or(0, |i| i + 1);
+        let (prefix, rest) = pattern.split_at(split);
+        let dir = normalize(from, prefix.trim_end_matches('/')).ok_or_else(|| {
+            anyhow!(
+                "{from}: cannot resolve {}, bare imports need --bundler esbuild",
+                quote(prefix)
+            )
+        })?;
+
+        let mut map = vec![];
+        for entry in WalkDir::new(join!(&self.root, &dir))
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            let path = entry.path().strip_prefix(&self.root)?;
+            let id = to_id(path);
+            let relative = id.strip_prefix(&dir).unwrap_or(&id).trim_start_matches('/');
+            if wildcard(rest, relative) {
+                map.push((format!("{prefix}{relative}"), id));
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+/// Minifies a bundle produced by [`Bundler::bundle`].
+pub fn minify(source: &str) -> Result<String> {
+    let allocator = Allocator::default();
+    let ret = Parser::new(&allocator, source, SourceType::cjs()).parse();
+    if let Some(error) = ret.diagnostics.errors().next() {
+        bail!("{error}");
+    }
+
+    let mut program = ret.program;
+    let ret = Minifier::new(MinifierOptions::default()).minify(&allocator, &mut program);
+
+    Ok(Codegen::new()
+        .with_options(CodegenOptions::minify())
+        .with_scoping(ret.scoping)
+        .build(&program)
+        .code)
+}
+
+fn quote(s: &str) -> String {
+    serde_json::to_string(s).unwrap()
+}
+
+fn to_id(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Joins a relative `spec` onto the directory of the module `from`.
+fn normalize(from: &str, spec: &str) -> Option<String> {
+    if !(spec.starts_with("./") || spec.starts_with("../") || spec == "." || spec == "..") {
+        return None;
+    }
+
+    let mut parts: Vec<&str> = from.split('/').collect();
+    parts.pop();
+    for part in spec.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+
+    Some(parts.join("/"))
+}
+
+fn wildcard(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((head, tail)) => {
+            let Some(text) = text.strip_prefix(head) else {
+                return false;
+            };
+            text.char_indices()
+                .map(|(i, _)| i)
+                .chain([text.len()])
+                .take_while(|&i| !text[..i].contains('/'))
+                .any(|i| wildcard(tail, &text[i..]))
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::{Context, Result, bail};
+use naql_shared::{join, own};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+pub const CONFIG_FILE: &str = "naql.toml";
+
+/// Build options read from `naql.toml`. Options given on the command line
+/// take precedence, paths are relative to the file itself.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// Directory of templates overlaid on the embedded runtime templates
+    pub templates: Option<PathBuf>,
+
+    #[serde(default)]
+    pub icon_themes: IconThemesConfig,
+
+    /// Acode plugin ids of VS Code extensions, an empty id drops the
+    /// dependency. Unlisted extensions keep their id, as naql does when
+    /// porting them.
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct IconThemesConfig {
+    /// What the plugin does the first time it is initialised
+    #[serde(default)]
+    pub first_install: FirstInstall,
+
+    /// Theme activated by `first-install = "activate"`, defaults to the first
+    /// contributed theme
+    pub activate: Option<String>,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FirstInstall {
+    /// Activate the theme of single-theme packs, open the picker otherwise
+    #[default]
+    Auto,
+    /// Open the "Preferences: File Icon Theme" picker
+    Picker,
+    /// Activate a specific theme
+    Activate,
+    /// Do nothing
+    None,
+}
+
+/// [`FirstInstall`] resolved against the contributed themes, as passed to the
+/// `iconThemes.js` template.
+#[derive(Serialize)]
+#[serde(tag = "mode", rename_all = "kebab-case")]
+pub enum OnFirstInstall {
+    Picker,
+    Activate { id: String },
+    None,
+}
+
+impl Config {
+    /// Reads `path`, or `naql.toml` in the current directory if it exists.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None if Path::new(CONFIG_FILE).is_file() => PathBuf::from(CONFIG_FILE),
+            None => return Ok(Self::default()),
+        };
+
+        debug!("reading config from {}", path.to_string_lossy());
+        let mut config: Self = toml::from_str(&read_to_string(&path)?)
+            .with_context(|| format!("invalid config {}", path.to_string_lossy()))?;
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        config.resolve(base);
+
+        Ok(config)
+    }
+
+    /// Acode plugin id of the VS Code extension `extension`, `None` if it has
+    /// no Acode counterpart.
+    pub fn plugin_id(&self, extension: &str) -> Option<String> {
+        let mapped = self
+            .dependencies
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(extension));
+        match mapped {
+            Some((_, id)) if id.is_empty() => None,
+            Some((_, id)) => Some(id.clone()),
+            // built into VS Code
+            None if extension.to_ascii_lowercase().starts_with("vscode.") => None,
+            None => Some(own!(extension)),
+        }
+    }
+
+    fn resolve(&mut self, base: &Path) {
+        if let Some(templates) = &self.templates {
+            self.templates = Some(join!(base, templates));
+        }
+    }
+}
+
+impl IconThemesConfig {
+    /// `details` are the `(id, label)` pairs of the contributed themes.
+    pub fn on_first_install(&self, details: &[(String, String)]) -> Result<OnFirstInstall> {
+        let Some((first, _)) = details.first() else {
+            return Ok(OnFirstInstall::None);
+        };
+
+        Ok(match self.first_install {
+            FirstInstall::Auto if details.len() == 1 => {
+                OnFirstInstall::Activate { id: first.clone() }
+            }
+            FirstInstall::Auto | FirstInstall::Picker => OnFirstInstall::Picker,
+            FirstInstall::Activate => {
+                let id = self.activate.clone().unwrap_or_else(|| first.clone());
+                if !details.iter().any(|(theme, _)| *theme == id) {
+                    bail!("icon-themes.activate: no icon theme with id {id}");
+                }
+                OnFirstInstall::Activate { id }
+            }
+            FirstInstall::None => OnFirstInstall::None,
+        })
+    }
+}
@@ -1,5 +1,5 @@
 pub use font_rule::FontRule;
-pub use style_rule::StyleRule;
+pub use style_rule::{Image, StyleRule};
 pub use style_sheet::*;
 
 mod css_rule;
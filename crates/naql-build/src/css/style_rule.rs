@@ -1,3 +1,4 @@
+use crate::sprite::Cell;
 use naql_shared::{manifest::vscode::icon_theme::DefinitionProperties, own};
 use std::fmt::Display;
 
@@ -5,6 +6,18 @@ use std::fmt::Display;
 pub struct StyleRule {
     pub selectors: Vec<String>,
     pub definition: DefinitionProperties,
+    /// Replaces `icon_path` in the CSS
+    pub image: Option<Image>,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Image {
+    DataUri(String),
+    /// Cell of the sprite at `url`
+    Sprite {
+        url: String,
+        cell: Cell,
+    },
 }
 
 impl StyleRule {
@@ -12,6 +25,7 @@ impl StyleRule {
         Self {
             selectors: vec![own!(selector)],
             definition,
+            image: None,
         }
     }
 }
@@ -37,8 +51,38 @@ impl Display for StyleRule {
             write!(f, "width:1em;")?;
         }
 
-        if let Some(icon_path) = &self.definition.icon_path {
-            write!(f, "background-image:url({});", icon_path.to_string_lossy())?;
+        match &self.image {
+            Some(Image::DataUri(data_uri)) => {
+                write!(f, "background-image:url(\"{data_uri}\");")?;
+            }
+            Some(Image::Sprite {
+                url,
+                cell: Cell::View(view),
+            }) => write!(f, "background-image:url({url}#{view});")?,
+            Some(Image::Sprite {
+                url,
+                cell:
+                    Cell::Grid {
+                        col,
+                        row,
+                        cols,
+                        rows,
+                    },
+            }) => {
+                write!(f, "background-image:url({url});")?;
+                write!(f, "background-size:{}% {}%;", cols * 100, rows * 100)?;
+                write!(
+                    f,
+                    "background-position:{}% {}%;",
+                    percent(*col, *cols),
+                    percent(*row, *rows)
+                )?;
+            }
+            None => {
+                if let Some(icon_path) = &self.definition.icon_path {
+                    write!(f, "background-image:url({});", icon_path.to_string_lossy())?;
+                }
+            }
         }
 
         if let Some(font_color) = &self.definition.font_color {
@@ -56,3 +100,12 @@ impl Display for StyleRule {
         write!(f, "}}")
     }
 }
+
+/// Background position of cell `i` out of `n`, in percent.
+fn percent(i: u32, n: u32) -> f64 {
+    if n < 2 {
+        return 0.0;
+    }
+
+    (i as f64 * 100_000.0 / (n - 1) as f64).round() / 1000.0
+}
@@ -1,13 +1,18 @@
-use super::StyleRule;
+use super::{Image, StyleRule};
+use crate::IconArgs;
+use crate::sprite::{Cell, png_atlas, svg_sprite};
+use crate::svg;
 use anyhow::{Result, bail};
+use base64::{Engine, engine::general_purpose::STANDARD};
 use cached::proc_macro::cached;
-use naql_shared::{join, ok};
+use naql_shared::vfs::Vfs;
+use naql_shared::{join, ok, own};
 use radix_fmt::radix_36;
 use rayon::prelude::*;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::ffi::OsStr;
 use std::fmt::Display;
-use std::fs::copy;
+use std::fs::{read, write};
 use std::path::PathBuf;
 use std::sync::Mutex;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -15,14 +20,21 @@ use tracing::debug;
 
 pub struct StyleSheet {
     rules: HashMap<String, Mutex<StyleRule>>,
+    /// Icons of the rules copied to `dest`
+    bundled: HashSet<PathBuf>,
+    vfs: Vfs,
+    options: IconArgs,
     src: PathBuf,
     dest: PathBuf,
 }
 
 impl StyleSheet {
-    pub fn new(src: PathBuf, dest: PathBuf) -> Self {
+    pub fn new(vfs: Vfs, options: IconArgs, src: PathBuf, dest: PathBuf) -> Self {
         Self {
             rules: HashMap::with_capacity(1024),
+            bundled: HashSet::new(),
+            vfs,
+            options,
             src,
             dest,
         }
@@ -30,29 +42,106 @@ impl StyleSheet {
 
     pub fn insert(&mut self, k: String, v: StyleRule) {
         if let Some(rule) = self.rules.get_mut(&k) {
-            ok!(rule.lock()).selectors.push(v.selectors[0].clone());
+            ok!(rule.get_mut()).selectors.push(v.selectors[0].clone());
         } else {
             self.rules.insert(k, Mutex::new(v));
         }
     }
 
     pub fn resolve_urls(&mut self) -> Result<()> {
-        self.rules
+        self.bundled = self
+            .rules
             .par_iter()
-            .map(|(_, rule)| -> Result<()> {
+            .filter_map(|(_, rule)| {
                 let mut rule = rule.lock().unwrap();
-                if let Some(icon_path) = &rule.definition.icon_path {
-                    let path = bundle(icon_path.clone(), self.src.clone(), self.dest.clone())?;
+                let path = bundle(
+                    &self.vfs,
+                    self.options,
+                    rule.definition.icon_path.clone()?,
+                    self.src.clone(),
+                    self.dest.clone(),
+                )
+                .ok()?;
 
-                    rule.definition.icon_path = Some(path);
+                if let Some(limit) = self.options.inline_icons {
+                    rule.image = data_uri(join!(&self.dest, &path), limit.bytes() as u64)
+                        .map(Image::DataUri);
                 }
-
-                Ok(())
+                rule.definition.icon_path = Some(path.clone());
+                Some(path)
             })
-            .all(|_| true);
+            .collect();
 
         Ok(())
     }
+
+    /// Packs the icons of the rules that aren't inlined, and the SVGs `svgs`,
+    /// into the sprites `<name>.sprite.svg` and `<name>.atlas.png`. Returns
+    /// where each icon packed is.
+    pub fn pack_sprites(
+        &mut self,
+        name: &str,
+        mut svgs: BTreeSet<PathBuf>,
+    ) -> Result<HashMap<PathBuf, (String, Cell)>> {
+        let mut pngs = BTreeSet::new();
+        for path in self.files() {
+            match path
+                .extension()
+                .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            {
+                Some(e) if e == "svg" => svgs.insert(path),
+                Some(e) if e == "png" => pngs.insert(path),
+                _ => false,
+            };
+        }
+
+        let mut cells = HashMap::new();
+        if !svgs.is_empty() {
+            let sprite = format!("{name}.sprite.svg");
+            let packed = svg_sprite(&self.dest, &Vec::from_iter(svgs), &sprite)?;
+            println!("packed {} SVG icons into {sprite}", packed.len());
+            cells.extend(packed.into_iter().map(|(k, c)| (k, (sprite.clone(), c))));
+        }
+        if !pngs.is_empty() {
+            let atlas = format!("{name}.atlas.png");
+            let packed = png_atlas(&self.dest, &Vec::from_iter(pngs), &atlas)?;
+            println!("packed {} PNG icons into {atlas}", packed.len());
+            cells.extend(packed.into_iter().map(|(k, c)| (k, (atlas.clone(), c))));
+        }
+
+        for rule in self.rules.values_mut() {
+            let rule = ok!(rule.get_mut());
+            if let Some((url, cell)) = rule
+                .definition
+                .icon_path
+                .as_ref()
+                .and_then(|p| cells.get(p))
+                && rule.image.is_none()
+            {
+                rule.image = Some(Image::Sprite {
+                    url: url.clone(),
+                    cell: cell.clone(),
+                });
+            }
+        }
+
+        Ok(cells)
+    }
+
+    /// Bundled icons the rules point at, rather than at a data URI or a
+    /// sprite.
+    pub fn files(&self) -> BTreeSet<PathBuf> {
+        self.rules
+            .values()
+            .filter_map(|rule| {
+                let rule = ok!(rule.lock());
+                rule.definition
+                    .icon_path
+                    .clone()
+                    .filter(|p| rule.image.is_none() && self.bundled.contains(p))
+            })
+            .collect()
+    }
 }
 
 impl Display for StyleSheet {
@@ -81,29 +170,82 @@ impl ParallelExtend<(String, StyleRule)> for StyleSheet {
 
 static COUNT: AtomicUsize = AtomicUsize::new(0);
 
-#[cached(result = true)]
-pub fn bundle(path: PathBuf, src: PathBuf, dest: PathBuf) -> Result<PathBuf> {
+/// Copies the icon at `path`, relative to `src` in `vfs`, to `dest` under a
+/// short unique name. Cached by everything the copy depends on, so themes
+/// sharing an icon share its file.
+#[cached(
+    result = true,
+    key = "(PathBuf, PathBuf, PathBuf, bool)",
+    convert = r#"{ (own!(vfs.location()), join!(&src, &path), dest.clone(), options.minify_svg) }"#
+)]
+pub fn bundle(
+    vfs: &Vfs,
+    options: IconArgs,
+    path: PathBuf,
+    src: PathBuf,
+    dest: PathBuf,
+) -> Result<PathBuf> {
     let src = join!(src, &path);
-    let src = match src.canonicalize() {
-        Ok(v) => v,
-        Err(_) => {
-            debug!(
-                "cp: cannot stat '{}': No such file or directory",
-                src.to_string_lossy()
-            );
-            bail!("{} does not exist", src.to_string_lossy())
-        }
-    };
+    if !vfs.is_file(&src) {
+        debug!(
+            "cp: cannot stat '{}': No such file or directory",
+            src.to_string_lossy()
+        );
+        bail!("{} does not exist", src.to_string_lossy())
+    }
     let path = PathBuf::from(format!(
         "{}.{}",
         radix_36(COUNT.fetch_add(1, Ordering::Relaxed)),
         path.extension()
-            .unwrap_or(&OsStr::new("jpg"))
+            .unwrap_or(OsStr::new("jpg"))
             .to_string_lossy()
     ));
 
     let d = join!(dest, &path);
-    debug!("cp {} {}", src.to_string_lossy(), d.to_string_lossy());
-    let _ = copy(src, d);
+    let is_svg = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("svg"));
+    if options.minify_svg && is_svg {
+        debug!("minify {} {}", src.to_string_lossy(), d.to_string_lossy());
+        write(d, svg::minify(vfs.read(src)?))?;
+    } else {
+        debug!("cp {} {}", src.to_string_lossy(), d.to_string_lossy());
+        let _ = vfs.copy(src, d);
+    }
     Ok(path)
 }
+
+/// The bundled SVG or PNG icon at `path` as a data URI, if it is at most
+/// `limit` bytes.
+#[cached]
+fn data_uri(path: PathBuf, limit: u64) -> Option<String> {
+    let ext = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    if path.metadata().ok()?.len() > limit {
+        return None;
+    }
+
+    let data = read(&path).ok()?;
+    match ext.as_str() {
+        // URL-encoded SVG is smaller than base64
+        "svg" => Some(format!(
+            "data:image/svg+xml,{}",
+            encode_svg(&String::from_utf8(data).ok()?)
+        )),
+        "png" => Some(format!("data:image/png;base64,{}", STANDARD.encode(data))),
+        _ => None,
+    }
+}
+
+/// Percent-encodes what can't appear in a double quoted `url()`.
+fn encode_svg(svg: &str) -> String {
+    let mut encoded = String::with_capacity(svg.len());
+    for c in svg.chars() {
+        match c {
+            '"' | '%' | '#' | '<' | '>' | '{' | '}' | '\\' | '\n' | '\r' | '\t' => {
+                encoded.push_str(&format!("%{:02X}", c as u32))
+            }
+            c => encoded.push(c),
+        }
+    }
+    encoded
+}
@@ -1,70 +1,213 @@
 #![allow(clippy::pedantic)]
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 pub use args::*;
-use naql_shared::manifest::acode::AcodeManifest;
+use config::Config;
+use naql_shared::manifest::acode::{AcodeManifest, PATH_FIELDS};
 use naql_shared::manifest::vscode::VsCodeManifest;
+use naql_shared::manifest::vsix::VsixManifest;
 use naql_shared::traits::ReadFromFile;
-use naql_shared::zip::{unzip, zip};
+use naql_shared::vfs::Vfs;
+use naql_shared::zip::{Method, ZipOptions, format_size, zip};
 use naql_shared::{join, manifest::vscode::icon_theme::IconThemeManifest};
 use rayon::prelude::*;
-use tempfile::TempDir;
 
 use minijinja::Environment;
 use naql_shared::{ok, own};
 use parser::Parser;
-use parser::icon_theme::IconThemeParser;
-use runtime::{Include, include_icon_themes, include_main, js_string};
-use tracing::{debug, instrument};
-use util::esbuild;
+use parser::icon_theme::{IconThemeParser, Icons};
+use report::SizeReport;
+use runtime::{
+    Feature, Include, check_engine, include_icon_themes, include_main, js_string, min_version_code,
+    overlay_templates,
+};
+use std::collections::HashSet;
+use std::fs::{create_dir_all, remove_file, write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, instrument, warn};
 
 mod args;
+mod bundler;
+mod config;
 mod css;
+mod lint;
 mod parser;
+mod report;
 mod runtime;
+mod sprite;
+mod svg;
 mod util;
+mod validate;
+
+pub use validate::validate;
+
+/// Name a custom entry point is bundled under, it may import the generated
+/// `./main.js`
+const ENTRY: &str = "entry.js";
+
+/// Output of contributions and the bundler packaged besides `main.js`
+const GENERATED: [&str; 2] = ["assets", "main.js.map"];
 
 pub struct Builder {
     args: BuildArgs,
+    zip: ZipOptions,
 }
 
 impl Builder {
     pub fn new(args: BuildArgs) -> Self {
-        Self { args }
+        Self {
+            args,
+            zip: ZipOptions::default(),
+        }
     }
 
     #[instrument(skip_all)]
     pub fn build(&mut self) -> Result<()> {
-        let input_path = self.args.path.canonicalize()?;
+        self.zip = self.zip_options()?;
+        let config = Config::load(self.args.config.as_deref())?;
+        let (id, members) = self.build_plugin(
+            &config,
+            &self.args.path,
+            &self.args.outfile,
+            self.args.manifest.as_deref(),
+        )?;
+
+        if let Some(pack_dir) = &self.args.pack_dir {
+            self.build_pack(&config, pack_dir, id, members)?;
+        }
 
-        let/*  mut */ tmp_dir = TempDir::with_prefix_in(".naql-", ".")?;
-        // tmp_dir.disable_cleanup(true);
+        Ok(())
+    }
+
+    fn zip_options(&self) -> Result<ZipOptions> {
+        let method = match self.args.compression {
+            Compression::Deflate => Method::Deflate,
+            Compression::Zstd => {
+                warn!("zstd compressed plugins can't be installed in Acode");
+                Method::Zstd
+            }
+            Compression::None => Method::Store,
+        };
+
+        ZipOptions::new(method, self.args.compression_level)
+    }
 
-        let src_dir = if input_path.is_file() {
-            unzip(input_path, tmp_dir.path())?;
-            join!(tmp_dir.path(), "extension")
+    /// Builds the members of an extension pack found in `dir`, and in turn
+    /// their members.
+    fn build_pack(
+        &self,
+        config: &Config,
+        dir: &Path,
+        id: String,
+        mut queue: Vec<String>,
+    ) -> Result<()> {
+        let mut built = HashSet::from([id.to_lowercase()]);
+
+        while let Some(id) = queue.pop() {
+            if !built.insert(id.to_lowercase()) {
+                continue;
+            }
+
+            let Some(path) = util::find_extension(dir, &id)? else {
+                warn!("{id}: not found in {}, skipping", dir.to_string_lossy());
+                continue;
+            };
+
+            println!();
+            println!("building {id} from {}", path.to_string_lossy());
+            let (_, members) =
+                self.build_plugin(config, &path, Path::new(&format!("{id}.zip")), None)?;
+            queue.extend(members);
+        }
+
+        Ok(())
+    }
+
+    /// Builds the extension at `path` into `outfile`. Returns the id of the
+    /// plugin and the extensions it depends on that are ported by naql.
+    fn build_plugin(
+        &self,
+        config: &Config,
+        path: &Path,
+        outfile: &Path,
+        manifest_path: Option<&Path>,
+    ) -> Result<(String, Vec<String>)> {
+        let input_path = path.canonicalize()?;
+        // a .vsix is read in place, without extracting it
+        let mut vfs = if input_path.is_file() {
+            Vfs::zip(&input_path, "extension")?
         } else {
-            input_path
+            Vfs::dir(&input_path)
         };
 
-        let vs_manifest = VsCodeManifest::read_from_file(join!(&src_dir, "package.json"))?;
-        debug!("Building plugin for {}", vs_manifest.display_name);
+        let vs_manifest = VsCodeManifest::read_from_vfs(&vfs, "package.json")?;
+        debug!("Building plugin for {}", vs_manifest.display_name());
+
+        if let Some(engine) = &vs_manifest.engines.vscode {
+            check_engine(engine);
+        }
+
+        let vsix = VsixManifest::find(&vfs)?;
 
         let mut manifest: AcodeManifest = vs_manifest.clone().into();
-        manifest.resolve(&src_dir);
-        if let Some(path) = &self.args.manifest {
-            let mut other = AcodeManifest::read_from_file(&path)?;
-            other.resolve(path.parent().unwrap());
-            manifest.merge(other);
+        manifest.discover(&vfs, vsix.as_ref());
+
+        let mut members = vec![];
+        let dependencies = manifest.dependencies.get_or_insert_default();
+        for extension in vs_manifest.dependencies() {
+            let Some(id) = config.plugin_id(extension) else {
+                debug!("{extension}: no acode plugin, skipping");
+                continue;
+            };
+            if id == *extension {
+                members.push(own!(extension));
+            }
+            if !dependencies.contains(&id) {
+                dependencies.push(id);
+            }
+        }
+
+        if let Some(path) = manifest_path {
+            let other = AcodeManifest::read_override(path)?;
+            // unlike those of the extension, the paths of an override are
+            // the user's, and may point anywhere
+            vfs.trust(ok!(path.canonicalize()?.parent()))?;
+            for field in PATH_FIELDS {
+                if let Some(p) = other.get(field).and_then(|p| p.as_str()) {
+                    vfs.trust(p)?;
+                }
+            }
+            manifest
+                .merge(other)
+                .with_context(|| format!("invalid manifest {}", path.to_string_lossy()))?;
+            manifest.override_dir = path.canonicalize()?.parent().map(Path::to_path_buf);
         }
 
-        let contributes = vs_manifest.contributes;
         let build_dir = util::build_dir(ok!(manifest.id.as_ref()))?;
+        if manifest.readme.is_none() {
+            warn!("no README found, generating one");
+            let readme = join!(&build_dir, "src", "README.md");
+            create_dir_all(join!(&build_dir, "src"))?;
+            write(&readme, vs_manifest.placeholder_readme())?;
+            vfs.trust(&readme)?;
+            manifest.readme = Some(readme);
+        }
+
+        let contributes = vs_manifest.contributes.clone();
         let mut include = Include::default();
 
         // Load templates
         let mut env = Environment::new();
         env.add_filter("js_string", js_string);
         minijinja_embed::load_templates!(&mut env);
+        if let Some(templates) = self.args.templates.as_ref().or(config.templates.as_ref()) {
+            if !templates.is_dir() {
+                bail!(
+                    "templates: {} is not a directory",
+                    templates.to_string_lossy()
+                );
+            }
+            overlay_templates(&mut env, templates)?;
+        }
 
         if let Some(icon_themes) = contributes.icon_themes {
             include.icon_themes = true;
@@ -72,38 +215,144 @@ impl Builder {
             util::contrib_dir(&build_dir, "iconThemes")?;
             let details = icon_themes
                 .into_par_iter()
-                .map(|info| -> Result<(String, String)> {
-                    let src = join!(&src_dir, info.path);
-                    let manifest = IconThemeManifest::read_from_file(&src)?;
+                .map(|info| -> Result<((String, String), Icons)> {
+                    let manifest = IconThemeManifest::read_from_vfs(&vfs, &info.path)?;
+                    lint::report(&info.path, &lint::icon_theme(&vfs, &info.path, &manifest));
                     let mut parser = IconThemeParser::new(
                         info.id.clone(),
-                        own!(src.parent().unwrap()),
+                        vfs.clone(),
+                        self.args.icons,
+                        own!(info.path.parent().unwrap()),
                         own!(&build_dir),
                         manifest,
                     );
-                    parser.parse()?;
+                    let icons = parser
+                        .parse()
+                        .with_context(|| format!("icon theme {}", info.id))?;
 
-                    Ok((info.id.clone(), info.label))
+                    Ok(((info.id.clone(), info.label), icons))
                 })
-                .filter_map(|x| x.ok())
+                .filter_map(|x| x.inspect_err(|e| warn!("{e:#}, skipping it")).ok())
                 .collect::<Vec<_>>();
+            let (details, icons): (Vec<_>, Vec<_>) = details.into_iter().unzip();
 
-            include_icon_themes(&mut env, details, &build_dir)?;
+            // themes share bundled icons, so one packed into a sprite is only
+            // dropped once no theme points at its file
+            let used = icons.iter().flat_map(|i| &i.used).collect::<HashSet<_>>();
+            let mut dropped = 0;
+            for path in icons.iter().flat_map(|i| &i.packed) {
+                if !used.contains(path)
+                    && remove_file(join!(&build_dir, "dist", "assets", path)).is_ok()
+                {
+                    dropped += 1;
+                }
+            }
+            if dropped > 0 {
+                println!("removed {dropped} icons replaced by sprites");
+            }
+
+            let stats = svg::take_stats();
+            if stats.files > 0 {
+                println!(
+                    "minified {} SVGs from {} to {}, saving {:.1}%",
+                    stats.files,
+                    format_size(stats.before),
+                    format_size(stats.after),
+                    100.0 * (stats.before - stats.after) as f64 / stats.before as f64
+                );
+            }
+
+            let first_install = config.icon_themes.on_first_install(&details)?;
+            include_icon_themes(&mut env, details, first_install, &build_dir)?;
         }
 
+        let mut features = include.features();
         include_main(&mut env, include, ok!(manifest.id.as_ref()), &build_dir)?;
-        esbuild(&build_dir)?;
-        manifest.bundle(join!(&build_dir, "dist"))?;
+        let custom = manifest
+            .main
+            .replace(own!("main.js"))
+            .filter(|main| main != "main.js")
+            .map(PathBuf::from);
+        let entry = match &custom {
+            Some(main) => {
+                util::copy_entry(&build_dir, main, ENTRY)?;
+                ENTRY
+            }
+            None => "main.js",
+        };
 
-        let output = join!(&self.args.outdir, &self.args.outfile);
-        let size = zip(build_dir, &output)?;
+        if manifest
+            .dependencies
+            .as_ref()
+            .is_some_and(|d| !d.is_empty())
+        {
+            features.push(Feature::Dependencies);
+        }
+        if custom.is_some() {
+            features.push(Feature::CustomEntry);
+        }
+        let (required, feature) = min_version_code(&features);
+        match manifest.min_version_code {
+            Some(code) if code < required => {
+                warn!("minVersionCode: {code} is lower than {required}, required by {feature}")
+            }
+            Some(_) => {}
+            None => manifest.min_version_code = Some(required),
+        }
+
+        let origin = custom.as_deref().and_then(Path::parent);
+        util::bundle(&build_dir, entry, origin, &self.args)?;
+        // generated alongside main.js
+        for generated in GENERATED {
+            if join!(&build_dir, "dist", generated).exists() {
+                let files = manifest.files.get_or_insert_default();
+                if !files.iter().any(|f| f == generated) {
+                    files.push(own!(generated));
+                }
+            }
+        }
+
+        manifest.bundle(&vfs, join!(&build_dir, "dist"), &vs_manifest)?;
+        validate::report(&manifest.validate(join!(&build_dir, "dist")))?;
+
+        let output = join!(&self.args.outdir, outfile);
+        let dist = join!(&build_dir, "dist");
+        let payload = manifest.payload(&dist)?;
+        println!();
+        println!("included:");
+        for path in &payload {
+            let slash = if join!(&dist, path).is_dir() { "/" } else { "" };
+            println!("  {}{slash}", path.to_string_lossy());
+        }
+
+        let report = SizeReport::from(zip(&dist, &output, &payload, self.zip)?);
+        // kept next to the build directory, which is cleared on every build
+        let report_path = join!(
+            ok!(build_dir.parent()),
+            format!("{id}.size.json", id = ok!(manifest.id.as_ref()))
+        );
+        report.print(SizeReport::load(&report_path).as_ref());
+
+        if let Some(max_size) = &self.args.max_size
+            && report.size > max_size.bytes() as u64
+        {
+            remove_file(&output)?;
+            bail!(
+                "{} is {}, over the budget of {}",
+                output.to_string_lossy(),
+                format_size(report.size),
+                format_size(max_size.bytes() as u64)
+            );
+        }
+        report.save(&report_path)?;
 
         println!();
         println!(
-            "written output to {} with size {size}",
-            output.to_string_lossy()
+            "written output to {} with size {}",
+            output.to_string_lossy(),
+            format_size(report.size)
         );
 
-        Ok(())
+        Ok((ok!(manifest.id), members))
     }
 }
@@ -0,0 +1,161 @@
+use naql_shared::diagnostic::{Diagnostics, Location, key_locations};
+use naql_shared::join;
+use naql_shared::manifest::vscode::icon_theme::{Defs, IconThemeManifest, Mapping};
+use naql_shared::vfs::Vfs;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tracing::warn;
+
+/// Checks the icon theme at `path`, parsed as `manifest`, for mappings to
+/// missing definitions, definitions whose icon is missing, keys duplicated
+/// ignoring case and unknown font ids.
+pub fn icon_theme(vfs: &Vfs, path: &Path, manifest: &IconThemeManifest) -> Diagnostics {
+    let source = vfs.read_to_string(path).unwrap_or_default();
+    let mut lint = Lint {
+        diagnostics: Diagnostics::default(),
+        locations: key_locations(&source),
+        definitions: &manifest.icon_definitions,
+    };
+
+    let defaults = [
+        ("file", &manifest.file),
+        ("folder", &manifest.folder),
+        ("folderExpanded", &manifest.folder_expanded),
+        ("rootFolder", &manifest.root_folder),
+        ("rootFolderExpanded", &manifest.root_folder_expanded),
+    ];
+    for (key, id) in defaults {
+        if let Some(id) = id {
+            lint.reference(&[key], id);
+        }
+    }
+
+    let mappings = [
+        ("fileExtensions", &manifest.file_extensions, true),
+        ("fileNames", &manifest.file_names, true),
+        ("folderNames", &manifest.folder_names, true),
+        ("folderNamesExpanded", &manifest.folder_names_expanded, true),
+        ("rootFolderNames", &manifest.root_folder_names, true),
+        (
+            "rootFolderNamesExpanded",
+            &manifest.root_folder_names_expanded,
+            true,
+        ),
+        // language ids are case sensitive
+        ("languageIds", &manifest.language_ids, false),
+    ];
+    for (key, mapping, ignore_case) in mappings {
+        lint.mapping(&[key], mapping, ignore_case);
+    }
+
+    for (key, section) in [
+        ("light", &manifest.light),
+        ("highContrast", &manifest.high_contrast),
+    ] {
+        for (field, value) in section.iter().flatten() {
+            match value {
+                Value::String(id) => lint.reference(&[key, field], id),
+                Value::Object(map) => {
+                    let mapping = map
+                        .iter()
+                        .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+                        .collect::<HashMap<_, _>>();
+                    lint.mapping(&[key, field], &Some(mapping), field != "languageIds");
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let base = path.parent().unwrap_or(Path::new(""));
+    let fonts = manifest
+        .fonts
+        .iter()
+        .flatten()
+        .map(|f| f.id.as_str())
+        .collect::<HashSet<_>>();
+    let mut definitions = manifest.icon_definitions.iter().collect::<Vec<_>>();
+    definitions.sort_by_key(|(id, _)| *id);
+    for (id, definition) in definitions {
+        if let Some(icon) = &definition.icon_path
+            && !vfs.is_file(join!(base, icon))
+        {
+            lint.warning(
+                &["iconDefinitions", id, "iconPath"],
+                format!("{} does not exist", icon.to_string_lossy()),
+            );
+        }
+
+        if let Some(font) = &definition.font_id
+            && !fonts.contains(font.as_str())
+        {
+            lint.warning(
+                &["iconDefinitions", id, "fontId"],
+                format!("no font with id `{font}`"),
+            );
+        }
+    }
+
+    lint.diagnostics
+}
+
+/// Logs `diagnostics` of the file at `path`.
+pub fn report(path: &Path, diagnostics: &Diagnostics) {
+    for diagnostic in diagnostics.warnings() {
+        warn!("{}:{diagnostic}", path.to_string_lossy());
+    }
+}
+
+struct Lint<'a> {
+    diagnostics: Diagnostics,
+    locations: HashMap<Vec<String>, Location>,
+    definitions: &'a Defs,
+}
+
+impl Lint<'_> {
+    fn reference(&mut self, path: &[&str], id: &str) {
+        if !self.definitions.contains_key(id) {
+            self.warning(path, format!("no icon definition `{id}`, it is ignored"));
+        }
+    }
+
+    fn mapping(&mut self, path: &[&str], mapping: &Mapping, ignore_case: bool) {
+        let Some(mapping) = mapping else {
+            return;
+        };
+
+        let mut keys = mapping.keys().collect::<Vec<_>>();
+        keys.sort();
+        let mut seen = HashMap::new();
+        for key in keys {
+            let key_path = path
+                .iter()
+                .copied()
+                .chain([key.as_str()])
+                .collect::<Vec<_>>();
+            self.reference(&key_path, &mapping[key]);
+
+            if !ignore_case {
+                continue;
+            }
+            match seen.get(&key.to_lowercase()) {
+                Some(first) => self.warning(
+                    &key_path,
+                    format!("duplicates `{first}` ignoring case, only one of them applies"),
+                ),
+                None => {
+                    seen.insert(key.to_lowercase(), key);
+                }
+            }
+        }
+    }
+
+    fn warning(&mut self, path: &[&str], message: String) {
+        let location = self
+            .locations
+            .get(&path.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+            .copied();
+        self.diagnostics.warning(path.join("."), message, location);
+    }
+}
@@ -1,38 +1,149 @@
 use super::Parser;
+use crate::IconArgs;
 use crate::css::{FolderType, FontRule, StyleRule, StyleSheet, bundle};
+use crate::sprite::Cell;
 use anyhow::Result;
+use naql_shared::manifest::vscode::icon_theme::{Defs, IconThemeManifest};
 use naql_shared::traits::WriteToFile;
-use naql_shared::{join, manifest::vscode::icon_theme::IconThemeManifest, ok, own};
+use naql_shared::vfs::Vfs;
+use naql_shared::{join, ok, own};
 use rayon::prelude::*;
+use std::collections::{BTreeSet, HashSet};
 use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::mem::take;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use tracing::debug;
+
+/// Pruned definitions listed by name, the rest are counted
+const PRUNED_LISTED: usize = 20;
+
+/// Bundled icons of a theme packed into its sprites, and those it still points
+/// at.
+#[derive(Default)]
+pub struct Icons {
+    pub packed: HashSet<PathBuf>,
+    pub used: HashSet<PathBuf>,
+}
 
 pub struct IconThemeParser {
     id: String,
+    vfs: Vfs,
+    options: IconArgs,
     src: PathBuf,
     build: PathBuf,
     manifest: IconThemeManifest,
 }
 
 impl IconThemeParser {
-    pub fn new(id: String, src: PathBuf, build: PathBuf, manifest: IconThemeManifest) -> Self {
+    pub fn new(
+        id: String,
+        vfs: Vfs,
+        options: IconArgs,
+        src: PathBuf,
+        build: PathBuf,
+        manifest: IconThemeManifest,
+    ) -> Self {
         Self {
             id,
+            vfs,
+            options,
             src,
             build,
             manifest,
         }
     }
+
+    /// Drops the definitions nothing is mapped to.
+    fn prune(&self, definitions: &mut Defs) {
+        let references = self.manifest.references();
+        let mut pruned = definitions
+            .keys()
+            .filter(|k| !references.contains(k.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        if pruned.is_empty() {
+            return;
+        }
+        for id in &pruned {
+            definitions.remove(id);
+        }
+
+        pruned.sort();
+        println!(
+            "pruned {} unused icon definitions from {}",
+            pruned.len(),
+            self.id
+        );
+        for id in pruned.iter().take(PRUNED_LISTED) {
+            println!("  {id}");
+        }
+        if pruned.len() > PRUNED_LISTED {
+            println!("  and {} more", pruned.len() - PRUNED_LISTED);
+        }
+        debug!("pruned from {}: {}", self.id, pruned.join(", "));
+    }
+
+    /// Packs the bundled icons into sprites, pointing the rules and the SVG
+    /// definitions mapped in the theme JSON at them. PNG atlases need the
+    /// CSS, so mapped PNG definitions keep their own file.
+    fn pack_sprites(
+        &self,
+        style_sheet: &mut StyleSheet,
+        definitions: &mut Defs,
+        bundled: &HashSet<PathBuf>,
+    ) -> Result<Icons> {
+        let mapped = [
+            &self.manifest.file_extensions,
+            &self.manifest.file_names,
+            &self.manifest.language_ids,
+        ]
+        .into_iter()
+        .flatten()
+        .flat_map(|m| m.values())
+        .collect::<HashSet<_>>();
+
+        let svgs = definitions
+            .iter()
+            .filter(|(id, _)| mapped.contains(id))
+            .filter_map(|(_, d)| d.icon_path.clone())
+            .filter(|p| bundled.contains(p))
+            .filter(|p| p.extension().is_some_and(|e| e.eq_ignore_ascii_case("svg")))
+            .collect::<BTreeSet<_>>();
+        let cells = style_sheet.pack_sprites(&self.id, svgs)?;
+
+        let mut icons = Icons {
+            packed: cells.keys().cloned().collect(),
+            used: style_sheet.files().into_iter().collect(),
+        };
+        for (id, definition) in definitions.iter_mut() {
+            let Some(path) = &definition.icon_path else {
+                continue;
+            };
+            match cells.get(path) {
+                Some((url, Cell::View(view))) => {
+                    definition.icon_path = Some(PathBuf::from(format!("{url}#{view}")));
+                }
+                _ if mapped.contains(id) => {
+                    icons.used.insert(path.clone());
+                }
+                _ => {}
+            }
+        }
+
+        Ok(icons)
+    }
 }
 
 impl Parser for IconThemeParser {
-    type Output = ();
+    type Output = Icons;
 
     fn parse(&mut self) -> anyhow::Result<Self::Output> {
         let mut definitions = take(&mut self.manifest.icon_definitions);
+        if !self.options.keep_unused_icons {
+            self.prune(&mut definitions);
+        }
 
         macro_rules! ensure {
             ($f:tt) => {
@@ -50,8 +161,12 @@ impl Parser for IconThemeParser {
         ensure!(root_folder);
         ensure!(root_folder_expanded);
 
-        let mut style_sheet =
-            StyleSheet::new(self.src.clone(), join!(&self.build, "dist", "assets"));
+        let mut style_sheet = StyleSheet::new(
+            self.vfs.clone(),
+            self.options,
+            self.src.clone(),
+            join!(&self.build, "dist", "assets"),
+        );
 
         macro_rules! get_or {
             ( $f:tt, $or:expr ) => {{
@@ -170,23 +285,25 @@ impl Parser for IconThemeParser {
 
         let mut definitions = Arc::into_inner(definitions).unwrap();
 
-        definitions
+        // icons that fail to bundle are reported by the lint
+        let bundled = definitions
             .par_iter_mut()
-            .map(|(_, definition)| -> Result<()> {
-                if let Some(icon_path) = &definition.icon_path {
-                    let path = bundle(
-                        icon_path.clone(),
-                        self.src.clone(),
-                        join!(&self.build, "dist", "assets"),
-                    )?;
-
-                    definition.icon_path = Some(path);
-                }
+            .filter_map(|(_, definition)| {
+                let path = bundle(
+                    &self.vfs,
+                    self.options,
+                    definition.icon_path.clone()?,
+                    self.src.clone(),
+                    join!(&self.build, "dist", "assets"),
+                )
+                .ok()?;
 
-                Ok(())
+                definition.icon_path = Some(path.clone());
+                Some(path)
             })
-            .all(|_| true);
+            .collect::<HashSet<_>>();
 
+        let mut icons = Icons::default();
         let s = if let Some(fonts) = &self.manifest.fonts {
             fonts
                 .clone()
@@ -194,6 +311,8 @@ impl Parser for IconThemeParser {
                 .map(|mut font| -> Result<String> {
                     for src in &mut font.src {
                         let path = bundle(
+                            &self.vfs,
+                            self.options,
                             src.path.clone(),
                             self.src.clone(),
                             join!(&self.build, "dist", "assets"),
@@ -210,6 +329,9 @@ impl Parser for IconThemeParser {
         } + &{
             let mut style_sheet = ok!(style_sheet.lock());
             style_sheet.resolve_urls()?;
+            if self.options.sprite {
+                icons = self.pack_sprites(&mut style_sheet, &mut definitions, &bundled)?;
+            }
             style_sheet.to_string()
         };
 
@@ -220,7 +342,7 @@ impl Parser for IconThemeParser {
             format!("{}.iconTheme.css", self.id)
         ))?);
 
-        f.write(s.as_bytes())?;
+        f.write_all(s.as_bytes())?;
 
         self.manifest.icon_definitions = definitions;
         self.manifest.icon_definitions.write_to_file(join!(
@@ -230,6 +352,6 @@ impl Parser for IconThemeParser {
             format!("{}.json", self.id)
         ))?;
 
-        Ok(())
+        Ok(icons)
     }
 }
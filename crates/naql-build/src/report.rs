@@ -0,0 +1,192 @@
+use anyhow::Result;
+use naql_shared::zip::{ZipEntry, ZipReport, format_size};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::fs::{read_to_string, write};
+use std::path::Path;
+
+/// Most grown entries listed against the previous build
+const GROWN: usize = 5;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Category {
+    Icons,
+    Fonts,
+    Css,
+    Js,
+    Json,
+    Other,
+}
+
+impl Category {
+    fn of(name: &str) -> Self {
+        let ext = Path::new(name)
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        match ext.as_str() {
+            "svg" | "png" | "jpg" | "jpeg" | "gif" | "webp" | "bmp" | "ico" => Self::Icons,
+            "woff" | "woff2" | "ttf" | "otf" | "eot" => Self::Fonts,
+            "css" => Self::Css,
+            "js" | "mjs" | "cjs" | "map" => Self::Js,
+            "json" => Self::Json,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl Display for Category {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Icons => "icons",
+            Self::Fonts => "fonts",
+            Self::Css => "CSS",
+            Self::Js => "JS",
+            Self::Json => "JSON",
+            Self::Other => "other",
+        })
+    }
+}
+
+/// Sizes of the files in a plugin zip, saved to compare the next build
+/// against.
+#[derive(Serialize, Deserialize)]
+pub struct SizeReport {
+    /// Size of the zip file
+    pub size: u64,
+    pub entries: Vec<ZipEntry>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Totals {
+    files: usize,
+    raw: u64,
+    compressed: u64,
+}
+
+impl From<ZipReport> for SizeReport {
+    fn from(report: ZipReport) -> Self {
+        Self {
+            size: report.size,
+            entries: report.entries,
+        }
+    }
+}
+
+impl SizeReport {
+    /// Report saved at `path` by a previous build, if any.
+    pub fn load<P: AsRef<Path>>(path: P) -> Option<Self> {
+        serde_json::from_str(&read_to_string(path).ok()?).ok()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Prints the sizes by category, and how they changed since `previous`.
+    pub fn print(&self, previous: Option<&Self>) {
+        let totals = self.totals();
+        let before = previous.map(|p| p.totals()).unwrap_or_default();
+
+        println!();
+        println!("size:");
+        let change = previous.map(|_| format!("{:>12}", "change"));
+        println!(
+            "  {:<10}{:>6}{:>12}{:>12}{}",
+            "category",
+            "files",
+            "raw",
+            "compressed",
+            change.unwrap_or_default()
+        );
+
+        let mut categories = totals.keys().chain(before.keys()).collect::<Vec<_>>();
+        categories.sort();
+        categories.dedup();
+        for category in categories {
+            let now = totals.get(category).copied().unwrap_or_default();
+            let change = previous.map(|_| {
+                let then = before.get(category).copied().unwrap_or_default();
+                delta(then.compressed, now.compressed)
+            });
+            row(&category.to_string(), now, change);
+        }
+
+        // the zip file, headers included
+        let total = Totals {
+            files: self.entries.len(),
+            raw: self.entries.iter().map(|e| e.raw).sum(),
+            compressed: self.size,
+        };
+        row("total", total, previous.map(|p| delta(p.size, self.size)));
+
+        if let Some(previous) = previous {
+            self.print_grown(previous);
+        }
+    }
+
+    fn print_grown(&self, previous: &Self) {
+        let before = previous
+            .entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.compressed))
+            .collect::<HashMap<_, _>>();
+
+        let mut grown = self
+            .entries
+            .iter()
+            .filter_map(|e| {
+                let then = before.get(e.name.as_str()).copied();
+                let growth = e.compressed.checked_sub(then.unwrap_or(0))?;
+                (growth > 0).then_some((e, then, growth))
+            })
+            .collect::<Vec<_>>();
+        if grown.is_empty() {
+            return;
+        }
+        grown.sort_by_key(|(_, _, growth)| Reverse(*growth));
+
+        println!();
+        println!("grown since the previous build:");
+        for (entry, then, growth) in grown.iter().take(GROWN) {
+            let new = if then.is_none() { " (new)" } else { "" };
+            println!("  {} +{}{new}", entry.name, format_size(*growth));
+        }
+        if grown.len() > GROWN {
+            println!("  and {} more", grown.len() - GROWN);
+        }
+    }
+
+    fn totals(&self) -> BTreeMap<Category, Totals> {
+        let mut totals = BTreeMap::<Category, Totals>::new();
+        for entry in &self.entries {
+            let totals = totals.entry(Category::of(&entry.name)).or_default();
+            totals.files += 1;
+            totals.raw += entry.raw;
+            totals.compressed += entry.compressed;
+        }
+
+        totals
+    }
+}
+
+fn row(name: &str, totals: Totals, change: Option<String>) {
+    println!(
+        "  {name:<10}{:>6}{:>12}{:>12}{}",
+        totals.files,
+        format_size(totals.raw),
+        format_size(totals.compressed),
+        change.map(|c| format!("{c:>12}")).unwrap_or_default()
+    );
+}
+
+fn delta(then: u64, now: u64) -> String {
+    match now.cmp(&then) {
+        std::cmp::Ordering::Equal => "-".to_string(),
+        std::cmp::Ordering::Greater => format!("+{}", format_size(now - then)),
+        std::cmp::Ordering::Less => format!("-{}", format_size(then - now)),
+    }
+}
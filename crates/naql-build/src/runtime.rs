@@ -1,10 +1,53 @@
+use crate::config::OnFirstInstall;
 use anyhow::Result;
 use minijinja::{Environment, Value, context};
-use naql_shared::join;
+use naql_shared::{join, ok};
+use semver::{Op, Version, VersionReq};
 use serde::Serialize;
-use std::fs::File;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{File, read_to_string};
 use std::io::{BufWriter, Write};
 use std::path::Path;
+use tracing::{debug, warn};
+use walkdir::WalkDir;
+
+/// Newest VS Code API implemented by the vscode-api plugin
+pub const VSCODE_API_VERSION: Version = Version::new(1, 103, 0);
+
+/// What a generated plugin may use from Acode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feature {
+    /// `setPluginInit` and `setPluginUnmount`, through which every plugin is
+    /// loaded
+    Runtime,
+    /// `dependencies` of plugin.json, installing vscode-api with the plugin
+    Dependencies,
+    /// File icon themes registered through vscode-api
+    IconThemes,
+    /// A custom entry point, calling Acode APIs of its own
+    CustomEntry,
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Feature::Runtime => "the plugin runtime",
+            Feature::Dependencies => "plugin dependencies",
+            Feature::IconThemes => "icon themes",
+            Feature::CustomEntry => "the custom entry point",
+        })
+    }
+}
+
+/// Lowest Acode version code each feature runs on.
+const VERSION_CODES: [(Feature, i32); 4] = [
+    // the minVersionCode of vscode-api, which runs the features it provides
+    (Feature::Runtime, 290),
+    (Feature::Dependencies, 955),
+    (Feature::IconThemes, 290),
+    // assumed to stick to what vscode-api itself needs
+    (Feature::CustomEntry, 290),
+];
 
 #[derive(Default, PartialEq, Serialize)]
 pub struct Include {
@@ -12,8 +55,50 @@ pub struct Include {
 }
 
 impl Include {
-    fn is_default(&self) -> bool {
-        *self == Include::default()
+    /// Features of Acode the runtime templates use.
+    pub fn features(&self) -> Vec<Feature> {
+        let mut features = vec![Feature::Runtime];
+        if self.icon_themes {
+            features.push(Feature::IconThemes);
+        }
+        features
+    }
+}
+
+/// Lowest Acode version code running all of `features`, and the feature
+/// requiring it. [`Feature::Runtime`] is always used.
+pub fn min_version_code(features: &[Feature]) -> (i32, Feature) {
+    ok!(VERSION_CODES
+        .into_iter()
+        .filter(|(feature, _)| *feature == Feature::Runtime || features.contains(feature))
+        .map(|(feature, code)| (code, feature))
+        .max_by_key(|(code, _)| *code))
+}
+
+/// Warns if the `engines.vscode` range of an extension excludes
+/// [`VSCODE_API_VERSION`].
+pub fn check_engine(engine: &str) {
+    let req = match VersionReq::parse(engine) {
+        Ok(req) if !req.matches(&VSCODE_API_VERSION) => req,
+        Ok(_) => return,
+        Err(e) => return warn!("engines.vscode: `{engine}` is not a version range: {e}"),
+    };
+
+    // the highest version any comparator requires at least
+    let lower = req
+        .comparators
+        .iter()
+        .filter(|c| !matches!(c.op, Op::Less | Op::LessEq))
+        .map(|c| Version::new(c.major, c.minor.unwrap_or(0), c.patch.unwrap_or(0)))
+        .max();
+    if lower.is_some_and(|lower| lower > VSCODE_API_VERSION) {
+        warn!(
+            "engines.vscode: `{engine}` requires VS Code APIs newer than vscode-api supports ({VSCODE_API_VERSION})"
+        );
+    } else {
+        warn!(
+            "engines.vscode: `{engine}` only allows VS Code versions older than the APIs vscode-api implements ({VSCODE_API_VERSION})"
+        );
     }
 }
 
@@ -21,15 +106,35 @@ pub fn js_string(value: Value) -> String {
     format!("`{}`", value.as_str().unwrap())
 }
 
+/// Adds every file under `dir` as a template named by its path relative to
+/// `dir`, replacing embedded templates of the same name.
+pub fn overlay_templates(env: &mut Environment, dir: &Path) -> Result<()> {
+    for entry in WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+    {
+        let name = entry
+            .path()
+            .strip_prefix(dir)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        debug!("overlay template {name}");
+        env.add_template_owned(name, read_to_string(entry.path())?)?;
+    }
+
+    Ok(())
+}
+
 pub fn include_main(
     env: &mut Environment,
     include: Include,
     id: &str,
     build_dir: &Path,
 ) -> Result<()> {
-    if include.is_default() {
-        return Ok(());
-    }
     let main = env.get_template("main.js")?;
     let main = main.render(context! {
         include, id
@@ -37,7 +142,7 @@ pub fn include_main(
 
     let mut f = BufWriter::new(File::create(join!(build_dir, "src", "main.js"))?);
 
-    f.write(main.as_bytes())?;
+    f.write_all(main.as_bytes())?;
 
     Ok(())
 }
@@ -45,16 +150,17 @@ pub fn include_main(
 pub fn include_icon_themes(
     env: &mut Environment,
     details: Vec<(String, String)>,
+    first_install: OnFirstInstall,
     build_dir: &Path,
 ) -> Result<()> {
     let icon_themes = env.get_template("iconThemes.js")?;
     let icon_themes = icon_themes.render(context! {
-        details
+        details, first_install
     })?;
 
     let mut f = BufWriter::new(File::create(join!(build_dir, "src", "iconThemes.js"))?);
 
-    f.write(icon_themes.as_bytes())?;
+    f.write_all(icon_themes.as_bytes())?;
 
     Ok(())
 }
@@ -0,0 +1,248 @@
+use anyhow::{Context, Result};
+use image::imageops::{FilterType, overlay};
+use image::{ImageFormat, RgbaImage};
+use naql_shared::{join, ok};
+use regex::{Captures, Regex};
+use roxmltree::{Document, ParsingOptions};
+use std::collections::HashMap;
+use std::fs::{read, read_to_string, write};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use tracing::warn;
+
+/// Cells of a PNG atlas are at most this many pixels wide and high
+const MAX_CELL: u32 = 128;
+
+/// Attributes of an icon's root replaced in the sprite
+const REPLACED: [&str; 7] = ["x", "y", "width", "height", "viewBox", "version", "id"];
+
+/// Size of icons without a `viewBox`, `width` or `height`
+const DEFAULT_SIZE: f64 = 16.0;
+
+static ID: LazyLock<Regex> =
+    LazyLock::new(|| ok!(Regex::new(r#"\bid\s*=\s*(?:"([^"]*)"|'([^']*)')"#)));
+static CLASS: LazyLock<Regex> =
+    LazyLock::new(|| ok!(Regex::new(r#"\bclass\s*=\s*(?:"([^"]*)"|'([^']*)')"#)));
+static STYLE: LazyLock<Regex> =
+    LazyLock::new(|| ok!(Regex::new(r"(?s)(<style\b[^>]*>)(.*?)(</style\s*>)")));
+static SELECTOR: LazyLock<Regex> = LazyLock::new(|| ok!(Regex::new(r"([.#])(-?[_a-zA-Z][\w-]*)")));
+static REF: LazyLock<Regex> = LazyLock::new(|| {
+    ok!(Regex::new(
+        r#"(url\(\s*['"]?#|href\s*=\s*["']#)([^'")\s]+)"#
+    ))
+});
+
+/// Where an icon is in a sprite.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Cell {
+    /// `<view>` of an SVG sprite
+    View(String),
+    /// Column and row of a PNG atlas
+    Grid {
+        col: u32,
+        row: u32,
+        cols: u32,
+        rows: u32,
+    },
+}
+
+/// Stacks the SVGs at `icons`, relative to `dir`, into the sprite `name` in
+/// `dir`, each shown through a `<view>`. Icons that can't be read or parsed
+/// are left out.
+pub fn svg_sprite(dir: &Path, icons: &[PathBuf], name: &str) -> Result<HashMap<PathBuf, Cell>> {
+    let mut cells = HashMap::new();
+    let mut body = String::new();
+    let mut y = 0.0;
+    let mut width: f64 = 0.0;
+
+    for (i, icon) in icons.iter().enumerate() {
+        let svg = match read_to_string(join!(dir, icon)) {
+            Ok(svg) => svg,
+            Err(e) => {
+                warn!("sprite: cannot read {}: {e}", icon.to_string_lossy());
+                continue;
+            }
+        };
+        let options = ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        };
+        let doc = match Document::parse_with_options(&svg, options) {
+            Ok(doc) => doc,
+            Err(e) => {
+                warn!("sprite: cannot parse {}: {e}", icon.to_string_lossy());
+                continue;
+            }
+        };
+
+        let root = doc.root_element();
+        let (w, h) = size(&root);
+        let view_box = root
+            .attribute("viewBox")
+            .map_or_else(|| format!("0 0 {w} {h}"), |v| v.to_string());
+
+        body.push_str(&format!(
+            r#"<svg x="0" y="{y}" width="{w}" height="{h}" viewBox="{view_box}""#
+        ));
+        for ns in root.namespaces() {
+            match ns.name() {
+                Some("xml") => {}
+                Some(prefix) => body.push_str(&format!(r#" xmlns:{prefix}="{}""#, ns.uri())),
+                None => body.push_str(&format!(r#" xmlns="{}""#, ns.uri())),
+            }
+        }
+        // presentation attributes of the root are inherited by the icon
+        for attr in root.attributes() {
+            let name = match attr.namespace().and_then(|ns| root.lookup_prefix(ns)) {
+                Some(prefix) => format!("{prefix}:{}", attr.name()),
+                None if REPLACED.contains(&attr.name()) => continue,
+                None => attr.name().to_string(),
+            };
+            let value = attr.value().replace('&', "&amp;").replace('"', "&quot;");
+            body.push_str(&format!(r#" {name}="{value}""#));
+        }
+        body.push('>');
+
+        // ids and classes are unique to each icon, not to the sprite
+        let inner = match (root.first_child(), root.last_child()) {
+            (Some(first), Some(last)) => &svg[first.range().start..last.range().end],
+            _ => "",
+        };
+        let prefix = format!("i{i}-");
+        let inner = ID.replace_all(inner, |c: &Captures| {
+            let id = c.get(1).or_else(|| c.get(2)).map_or("", |m| m.as_str());
+            format!(r#"id="{prefix}{id}""#)
+        });
+        let inner = REF.replace_all(&inner, |c: &Captures| format!("{}{prefix}{}", &c[1], &c[2]));
+        let inner = CLASS.replace_all(&inner, |c: &Captures| {
+            let classes = c.get(1).or_else(|| c.get(2)).map_or("", |m| m.as_str());
+            let classes = classes
+                .split_whitespace()
+                .map(|class| format!("{prefix}{class}"))
+                .collect::<Vec<_>>();
+            format!(r#"class="{}""#, classes.join(" "))
+        });
+        let inner = STYLE.replace_all(&inner, |c: &Captures| {
+            format!("{}{}{}", &c[1], scope_style(&c[2], &prefix), &c[3])
+        });
+        body.push_str(&inner);
+        body.push_str("</svg>");
+
+        let view = format!("v{i}");
+        body.push_str(&format!(r#"<view id="{view}" viewBox="0 {y} {w} {h}"/>"#));
+        cells.insert(icon.clone(), Cell::View(view));
+
+        y += h;
+        width = width.max(w);
+    }
+
+    write(
+        join!(dir, name),
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{y}" viewBox="0 0 {width} {y}">{body}</svg>"#
+        ),
+    )?;
+
+    Ok(cells)
+}
+
+/// Packs the PNGs at `icons`, relative to `dir`, into the atlas `name` in
+/// `dir`, a grid of equally sized cells. Icons that can't be decoded are
+/// left out.
+pub fn png_atlas(dir: &Path, icons: &[PathBuf], name: &str) -> Result<HashMap<PathBuf, Cell>> {
+    let images = icons
+        .iter()
+        .filter_map(|icon| {
+            let data = read(join!(dir, icon)).ok()?;
+            match image::load_from_memory(&data) {
+                Ok(image) => Some((icon, image)),
+                Err(e) => {
+                    warn!("sprite: cannot decode {}: {e}", icon.to_string_lossy());
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let cell = images
+        .iter()
+        .map(|(_, image)| image.width().max(image.height()))
+        .max()
+        .unwrap_or(1)
+        .min(MAX_CELL);
+    let cols = (images.len() as f64).sqrt().ceil().max(1.0) as u32;
+    let rows = (images.len() as u32).div_ceil(cols).max(1);
+
+    let mut atlas = RgbaImage::new(cols * cell, rows * cell);
+    let mut cells = HashMap::new();
+    for (i, (icon, image)) in images.into_iter().enumerate() {
+        let image = if image.width() > cell || image.height() > cell {
+            image.resize(cell, cell, FilterType::Lanczos3)
+        } else {
+            image
+        };
+
+        let (col, row) = (i as u32 % cols, i as u32 / cols);
+        // centered in its cell
+        let x = col * cell + (cell - image.width()) / 2;
+        let y = row * cell + (cell - image.height()) / 2;
+        overlay(&mut atlas, &image.to_rgba8(), x.into(), y.into());
+        cells.insert(
+            icon.clone(),
+            Cell::Grid {
+                col,
+                row,
+                cols,
+                rows,
+            },
+        );
+    }
+
+    atlas
+        .save_with_format(join!(dir, name), ImageFormat::Png)
+        .with_context(|| format!("sprite: cannot write {name}"))?;
+
+    Ok(cells)
+}
+
+/// Prefixes the classes and ids in the selectors of the style sheet `css`,
+/// leaving declarations and at-rules alone.
+fn scope_style(css: &str, prefix: &str) -> String {
+    let mut scoped = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(end) = rest.find(['{', '}']) {
+        let (part, brace) = (&rest[..end], &rest[end..=end]);
+        if brace == "{" && !part.trim_start().starts_with('@') {
+            scoped.push_str(
+                &SELECTOR.replace_all(part, |c: &Captures| format!("{}{prefix}{}", &c[1], &c[2])),
+            );
+        } else {
+            scoped.push_str(part);
+        }
+        scoped.push_str(brace);
+        rest = &rest[end + 1..];
+    }
+    scoped.push_str(rest);
+    scoped
+}
+
+/// Width and height of an icon, from its `viewBox` or `width` and `height`.
+fn size(root: &roxmltree::Node) -> (f64, f64) {
+    let view_box = root.attribute("viewBox").and_then(|v| {
+        let v = v
+            .split([' ', ','])
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<f64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        (v.len() == 4).then(|| (v[2], v[3]))
+    });
+
+    view_box.unwrap_or_else(|| {
+        let length = |name| {
+            root.attribute(name)
+                .and_then(|v: &str| v.trim_end_matches("px").parse::<f64>().ok())
+                .unwrap_or(DEFAULT_SIZE)
+        };
+        (length("width"), length("height"))
+    })
+}
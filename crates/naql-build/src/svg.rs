@@ -0,0 +1,425 @@
+use naql_shared::{ok, own};
+use regex::{Captures, Regex};
+use roxmltree::{Document, Node, NodeType, ParsingOptions};
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::debug;
+
+/// Significant digits numbers in geometry are rounded to, relative to each
+/// number so that icons drawn in small coordinate spaces keep their shape
+const PRECISION: usize = 5;
+
+const SVG_NS: &str = "http://www.w3.org/2000/svg";
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Namespaces of editor and metadata markup, dropped with everything in them
+const EDITOR_NS: [&str; 10] = [
+    "http://www.inkscape.org/namespaces/inkscape",
+    "http://sodipodi.sourceforge.net/DTD/sodipodi-0.dtd",
+    "http://www.bohemiancoding.com/sketch/ns",
+    "http://ns.adobe.com/",
+    "http://www.serif.com/",
+    "http://www.figma.com/figma/ns",
+    "http://www.vectornator.io",
+    "http://purl.org/dc/elements/1.1/",
+    "http://creativecommons.org/ns#",
+    "http://www.w3.org/1999/02/22-rdf-syntax-ns#",
+];
+
+/// Commands of path data
+const PATH_COMMANDS: &str = "MmLlHhVvCcSsQqTtAaZz";
+
+/// Attributes holding numbers that are safe to round, besides path data
+const NUMERIC: [&str; 25] = [
+    "points",
+    "viewBox",
+    "transform",
+    "gradientTransform",
+    "patternTransform",
+    "x",
+    "y",
+    "x1",
+    "y1",
+    "x2",
+    "y2",
+    "cx",
+    "cy",
+    "fx",
+    "fy",
+    "r",
+    "rx",
+    "ry",
+    "width",
+    "height",
+    "stroke-width",
+    "offset",
+    "opacity",
+    "fill-opacity",
+    "stroke-opacity",
+];
+
+/// Elements whose text is rendered, only their whitespace is collapsed
+const TEXT: [&str; 3] = ["text", "tspan", "textPath"];
+
+static NUMBER: LazyLock<Regex> =
+    LazyLock::new(|| ok!(Regex::new(r"[-+]?(?:\d+\.?\d*|\.\d+)(?:[eE][-+]?\d+)?")));
+static WHITESPACE: LazyLock<Regex> = LazyLock::new(|| ok!(Regex::new(r"\s+")));
+
+static FILES: AtomicU64 = AtomicU64::new(0);
+static BEFORE: AtomicU64 = AtomicU64::new(0);
+static AFTER: AtomicU64 = AtomicU64::new(0);
+
+/// Bytes saved by [`minify`] since the last call to [`take_stats`].
+pub struct Stats {
+    pub files: u64,
+    pub before: u64,
+    pub after: u64,
+}
+
+pub fn take_stats() -> Stats {
+    Stats {
+        files: FILES.swap(0, Ordering::Relaxed),
+        before: BEFORE.swap(0, Ordering::Relaxed),
+        after: AFTER.swap(0, Ordering::Relaxed),
+    }
+}
+
+/// Strips comments, metadata and editor markup from `svg`, collapses
+/// whitespace and rounds numbers. `svg` is returned as is if it can't be
+/// parsed or doesn't get smaller.
+pub fn minify(svg: Vec<u8>) -> Vec<u8> {
+    let Some(minified) = std::str::from_utf8(&svg).ok().and_then(|s| {
+        let options = ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        };
+        match Document::parse_with_options(s, options) {
+            Ok(doc) => Some(write_document(&doc)),
+            Err(e) => {
+                debug!("svg: cannot parse, keeping it as is: {e}");
+                None
+            }
+        }
+    }) else {
+        return svg;
+    };
+
+    let before = svg.len() as u64;
+    let minified = if minified.len() < svg.len() {
+        minified.into_bytes()
+    } else {
+        svg
+    };
+
+    FILES.fetch_add(1, Ordering::Relaxed);
+    BEFORE.fetch_add(before, Ordering::Relaxed);
+    AFTER.fetch_add(minified.len() as u64, Ordering::Relaxed);
+    minified
+}
+
+fn write_document(doc: &Document) -> String {
+    let root = doc.root_element();
+
+    // every namespace is declared once, on the root
+    let mut namespaces = BTreeMap::new();
+    for node in root
+        .descendants()
+        .filter(|n| n.is_element() && !is_editor(n))
+    {
+        let uris = node
+            .tag_name()
+            .namespace()
+            .into_iter()
+            .chain(node.attributes().filter_map(|a| a.namespace()));
+        for uri in uris {
+            if uri == XML_NS
+                || EDITOR_NS.iter().any(|ns| uri.starts_with(ns))
+                || namespaces.contains_key(uri)
+            {
+                continue;
+            }
+
+            // SVG is the default namespace, others need a prefix to not clash
+            // with it
+            let prefix = match node.lookup_prefix(uri) {
+                _ if uri == SVG_NS => None,
+                Some(prefix) => Some(own!(prefix)),
+                None => Some(format!("ns{}", namespaces.len())),
+            };
+            namespaces.insert(uri, prefix);
+        }
+    }
+
+    let mut out = String::with_capacity(doc.input_text().len());
+    write_element(&mut out, root, &namespaces, true);
+    out
+}
+
+fn write_element(
+    out: &mut String,
+    node: Node,
+    namespaces: &BTreeMap<&str, Option<String>>,
+    root: bool,
+) {
+    let name = qualified(
+        node.tag_name().namespace(),
+        node.tag_name().name(),
+        namespaces,
+    );
+    out.push('<');
+    out.push_str(&name);
+
+    if root {
+        for (uri, prefix) in namespaces {
+            match prefix {
+                Some(prefix) => out.push_str(&format!(" xmlns:{prefix}=\"")),
+                None => out.push_str(" xmlns=\""),
+            }
+            escape(out, uri, true);
+            out.push('"');
+        }
+    }
+
+    for attr in node.attributes() {
+        if attr
+            .namespace()
+            .is_some_and(|ns| EDITOR_NS.iter().any(|e| ns.starts_with(e)))
+        {
+            continue;
+        }
+        // unused by renderers
+        if root && attr.namespace().is_none() && attr.name() == "version" {
+            continue;
+        }
+
+        let value = if attr.namespace().is_none() && attr.name() == "d" {
+            round_path(attr.value()).unwrap_or_else(|| collapse(attr.value()).into_owned())
+        } else if attr.namespace().is_none() && NUMERIC.contains(&attr.name()) {
+            round_numbers(attr.value())
+        } else {
+            collapse(attr.value()).into_owned()
+        };
+
+        out.push(' ');
+        out.push_str(&qualified(attr.namespace(), attr.name(), namespaces));
+        out.push_str("=\"");
+        escape(out, value.trim(), true);
+        out.push('"');
+    }
+
+    let children = node
+        .children()
+        .filter(|c| match c.node_type() {
+            NodeType::Element => !is_editor(c),
+            NodeType::Text => true,
+            _ => false,
+        })
+        .collect::<Vec<_>>();
+    if children.is_empty() {
+        out.push_str("/>");
+        return;
+    }
+
+    out.push('>');
+    let text = TEXT.contains(&node.tag_name().name());
+    for child in children {
+        if child.is_element() {
+            write_element(out, child, namespaces, false);
+        } else if let Some(s) = child.text() {
+            let s = collapse(s);
+            if text {
+                escape(out, &s, false);
+            } else if !s.trim().is_empty() {
+                escape(out, s.trim(), false);
+            }
+        }
+    }
+    out.push_str("</");
+    out.push_str(&name);
+    out.push('>');
+}
+
+fn is_editor(node: &Node) -> bool {
+    let ns = node.tag_name().namespace();
+    (ns == Some(SVG_NS) && node.tag_name().name() == "metadata")
+        || ns.is_some_and(|ns| EDITOR_NS.iter().any(|e| ns.starts_with(e)))
+}
+
+fn qualified(
+    namespace: Option<&str>,
+    name: &str,
+    namespaces: &BTreeMap<&str, Option<String>>,
+) -> String {
+    match namespace {
+        Some(XML_NS) => format!("xml:{name}"),
+        Some(uri) => match namespaces.get(uri) {
+            Some(Some(prefix)) => format!("{prefix}:{name}"),
+            _ => name.to_string(),
+        },
+        None => name.to_string(),
+    }
+}
+
+fn collapse(s: &str) -> std::borrow::Cow<'_, str> {
+    WHITESPACE.replace_all(s, " ")
+}
+
+/// Rounds the numbers in `value` to [`PRECISION`] significant digits.
+fn round_numbers(value: &str) -> String {
+    let value = collapse(value);
+    NUMBER
+        .replace_all(&value, |c: &Captures| {
+            let m = ok!(c.get(0));
+            let mut rounded = round(m.as_str());
+            // `1.00004.5` must not become `1.5`
+            if !rounded.contains(['.', 'e', 'E']) && value[m.end()..].starts_with('.') {
+                rounded.push(' ');
+            }
+            rounded
+        })
+        .into_owned()
+}
+
+/// Rounds the numbers in the path data `d` to [`PRECISION`] significant
+/// digits. The flags of arcs may be written without separators, as in
+/// `a2 2 0 01-2 2`, so they are read as single digits and written that way.
+/// `None` if `d` can't be parsed.
+fn round_path(d: &str) -> Option<String> {
+    let mut out = String::with_capacity(d.len());
+    let mut rest = d;
+    let mut command = None;
+    let mut args = 0;
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ',');
+        let Some(c) = rest.chars().next() else {
+            break;
+        };
+
+        if PATH_COMMANDS.contains(c) {
+            out.push(c);
+            command = Some(c);
+            args = 0;
+            rest = &rest[1..];
+            continue;
+        }
+
+        let arc = matches!(command?, 'A' | 'a');
+        let arg = if arc && matches!(args % 7, 3 | 4) {
+            if !matches!(c, '0' | '1') {
+                return None;
+            }
+            rest = &rest[1..];
+            c.to_string()
+        } else {
+            let m = NUMBER.find(rest).filter(|m| m.start() == 0)?;
+            rest = &rest[m.end()..];
+            round(m.as_str())
+        };
+
+        let after_flag = arc && matches!(args % 7, 4 | 5);
+        if args > 0 && !after_flag && !arg.starts_with('-') {
+            out.push(' ');
+        }
+        out.push_str(&arg);
+        args += 1;
+    }
+
+    Some(out)
+}
+
+/// Rounds the number `original` to [`PRECISION`] significant digits, dropping
+/// redundant zeros, or keeps it if that isn't shorter.
+fn round(original: &str) -> String {
+    let Ok(n) = original.parse::<f64>() else {
+        return own!(original);
+    };
+
+    // never into the integer part
+    let digits = if n == 0.0 {
+        0
+    } else {
+        n.abs().log10().floor() as i32 + 1
+    };
+    let decimals = (PRECISION as i32 - digits).max(0) as usize;
+    let n = format!("{n:.decimals$}").parse::<f64>().unwrap_or(n);
+    let mut rounded = n.to_string();
+    if rounded == "-0" {
+        rounded = own!("0");
+    }
+    if let Some(rest) = rounded.strip_prefix("0.") {
+        rounded = format!(".{rest}");
+    } else if let Some(rest) = rounded.strip_prefix("-0.") {
+        rounded = format!("-.{rest}");
+    }
+
+    if rounded.len() >= original.len() {
+        own!(original)
+    } else {
+        rounded
+    }
+}
+
+fn escape(out: &mut String, s: &str, attr: bool) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' if !attr => out.push_str("&gt;"),
+            '"' if attr => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arc_flags() {
+        assert_eq!(round_path("a1 1 0 014 4").as_deref(), Some("a1 1 0 014 4"));
+        assert_eq!(
+            round_path("a2,2 0 1,0 -2,2 a2 2 0 1 1 2 2").as_deref(),
+            Some("a2 2 0 10-2 2a2 2 0 112 2")
+        );
+        assert_eq!(
+            round_path("A1.123456 1 0 0 1 .5 .5").as_deref(),
+            Some("A1.1235 1 0 01.5 .5")
+        );
+        // flags are single digits
+        assert_eq!(round_path("a1 1 0 2 1 4 4"), None);
+    }
+
+    #[test]
+    fn exponents() {
+        assert_eq!(round("1e-5"), "1e-5");
+        assert_eq!(round("1.23456789e3"), "1234.6");
+        assert_eq!(round("0.000012345678"), ".000012346");
+        assert_eq!(round_path("M1e-5 2E+2").as_deref(), Some("M1e-5 200"));
+    }
+
+    #[test]
+    fn implicit_separators() {
+        assert_eq!(round_path("M.5.5L-1-2").as_deref(), Some("M.5 .5L-1-2"));
+        assert_eq!(
+            round_path("M0.1234567-0.1234567z").as_deref(),
+            Some("M.12346-.12346z")
+        );
+        assert_eq!(round_numbers("1.00004.5"), "1 .5");
+        assert_eq!(round_numbers("0,0 16,16"), "0,0 16,16");
+    }
+
+    #[test]
+    fn significant_digits() {
+        assert_eq!(round("3.14159265"), "3.1416");
+        assert_eq!(round("0.123456"), ".12346");
+        assert_eq!(round("-0.000001"), "-.000001");
+        assert_eq!(round("10.000001"), "10");
+        // never into the integer part
+        assert_eq!(round("123456.789"), "123457");
+        assert_eq!(round("1.5"), "1.5");
+        assert_eq!(round("-0.0000001"), "-.0000001");
+    }
+}
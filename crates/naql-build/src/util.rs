@@ -1,11 +1,15 @@
-use anyhow::Result;
-use naql_shared::join;
+use crate::bundler;
+use crate::{BuildArgs, Bundler, Profile, SourceMap};
+use anyhow::{Result, bail};
 use naql_shared::node::find_binary;
-use std::env::current_dir;
-use std::fs::{create_dir_all, remove_dir_all};
+use naql_shared::{join, ok};
+use semver::Version;
+use std::env::{current_dir, join_paths};
+use std::fs::{copy, create_dir_all, read_dir, remove_dir_all, write};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use tracing::debug;
+use walkdir::WalkDir;
 
 pub fn build_dir(id: &str) -> Result<PathBuf> {
     let pwd = current_dir()?;
@@ -15,11 +19,42 @@ pub fn build_dir(id: &str) -> Result<PathBuf> {
         remove_dir_all(&build)?;
     }
 
-    debug!("mkdir -p {}", build.to_string_lossy());
-    create_dir_all(&build)?;
+    for dir in ["src", "dist"] {
+        let dir = join!(&build, dir);
+        debug!("mkdir -p {}", dir.to_string_lossy());
+        create_dir_all(dir)?;
+    }
     Ok(build)
 }
 
+/// Finds the extension `id` in `dir`, as `<id>.vsix`, `<id>-<version>.vsix`
+/// or an `<id>` directory, ignoring case.
+pub fn find_extension(dir: &Path, id: &str) -> Result<Option<PathBuf>> {
+    let id = id.to_ascii_lowercase();
+    let mut found = read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| {
+            let name = ok!(path.file_name()).to_string_lossy().to_ascii_lowercase();
+            match name.strip_suffix(".vsix") {
+                Some(stem) if path.is_file() => {
+                    stem == id || stem.strip_prefix(&id).is_some_and(|v| v.starts_with('-'))
+                }
+                _ => path.is_dir() && name == id,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // the newest version
+    found.sort_by_key(|path| {
+        let name = ok!(path.file_name()).to_string_lossy().to_ascii_lowercase();
+        name.strip_suffix(".vsix")
+            .and_then(|stem| stem.get(id.len() + 1..))
+            .and_then(|version| Version::parse(version).ok())
+    });
+    Ok(found.pop())
+}
+
 pub fn contrib_dir<P: AsRef<Path>>(path: P, name: &str) -> Result<PathBuf> {
     let contrib = join!(&path, "src", name);
 
@@ -32,16 +67,119 @@ pub fn contrib_dir<P: AsRef<Path>>(path: P, name: &str) -> Result<PathBuf> {
     Ok(contrib)
 }
 
-pub fn esbuild(build_dir: &Path) -> anyhow::Result<()> {
-    Command::new(find_binary("esbuild")?)
-        .args([
-            "src/main.js",
-            "--bundle",
-            "--minify",
-            "--outfile=dist/main.js",
-        ])
-        .current_dir(build_dir)
-        .output()?;
+/// Copies the directory of the custom entry point `main` into `src/` of
+/// `build_dir`, so that its relative imports resolve, with `main` itself as
+/// `entry`. Hidden files and `node_modules` are left out.
+pub fn copy_entry(build_dir: &Path, main: &Path, entry: &str) -> Result<()> {
+    let dir = ok!(main.parent());
+    let src = join!(build_dir, "src");
+    copy(main, join!(&src, entry))?;
+
+    for e in WalkDir::new(dir)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name().to_string_lossy();
+            !name.starts_with('.') && name != "node_modules"
+        })
+    {
+        let e = e?;
+        if e.path() == main {
+            continue;
+        }
+
+        let path = e.path().strip_prefix(dir)?;
+        let target = join!(&src, path);
+        if e.file_type().is_dir() {
+            create_dir_all(target)?;
+        } else if target.exists() {
+            bail!(
+                "main: {} clashes with the generated src/{}",
+                e.path().to_string_lossy(),
+                path.to_string_lossy()
+            );
+        } else {
+            copy(e.path(), target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Bundles `src/<entry>` of `build_dir` into `dist/main.js`. Bare imports
+/// resolve against the `node_modules` of `origin`, the directory of a custom
+/// entry point, with esbuild.
+pub fn bundle(
+    build_dir: &Path,
+    entry: &str,
+    origin: Option<&Path>,
+    args: &BuildArgs,
+) -> Result<()> {
+    match args.bundler {
+        Bundler::Embedded => embedded(build_dir, entry, args.profile, args.sourcemap),
+        Bundler::Esbuild => esbuild(build_dir, entry, origin, args.profile, args.sourcemap),
+    }
+}
+
+pub fn embedded(
+    build_dir: &Path,
+    entry: &str,
+    profile: Profile,
+    sourcemap: SourceMap,
+) -> Result<()> {
+    let bundle = bundler::Bundler::new(join!(build_dir, "src")).bundle(entry)?;
+
+    let dist = join!(build_dir, "dist");
+    create_dir_all(&dist)?;
+
+    let code = match (profile, sourcemap) {
+        (Profile::Release, _) => bundler::minify(&bundle.code)?,
+        (Profile::Dev, SourceMap::External) => {
+            write(join!(&dist, "main.js.map"), &bundle.map)?;
+            format!("{}//# sourceMappingURL=main.js.map\n", bundle.code)
+        }
+        (Profile::Dev, SourceMap::Inline) => {
+            format!(
+                "{}//# sourceMappingURL={}\n",
+                bundle.code,
+                bundle.map_data_url()?
+            )
+        }
+    };
+    write(join!(dist, "main.js"), code)?;
+
+    Ok(())
+}
+
+pub fn esbuild(
+    build_dir: &Path,
+    entry: &str,
+    origin: Option<&Path>,
+    profile: Profile,
+    sourcemap: SourceMap,
+) -> anyhow::Result<()> {
+    let entry = format!("src/{entry}");
+    let mut args = vec![entry.as_str(), "--bundle", "--outfile=dist/main.js"];
+    match (profile, sourcemap) {
+        (Profile::Release, _) => args.push("--minify"),
+        (Profile::Dev, SourceMap::External) => args.push("--sourcemap"),
+        (Profile::Dev, SourceMap::Inline) => args.push("--sourcemap=inline"),
+    }
+
+    let mut command = Command::new(find_binary("esbuild")?);
+    command.args(args).current_dir(build_dir);
+    if let Some(origin) = origin {
+        let node_path = origin.ancestors().map(|dir| join!(dir, "node_modules"));
+        command.env("NODE_PATH", join_paths(node_path)?);
+    }
+    let output = command.output()?;
+
+    if !output.status.success() {
+        bail!(
+            "esbuild: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
 
     Ok(())
 }
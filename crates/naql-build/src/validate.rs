@@ -0,0 +1,56 @@
+use crate::ValidateArgs;
+use anyhow::{Result, bail};
+use naql_shared::diagnostic::Diagnostics;
+use naql_shared::join;
+use naql_shared::manifest::acode::AcodeManifest;
+use naql_shared::traits::ReadFromFile;
+use naql_shared::zip::unzip;
+use std::path::PathBuf;
+use tempfile::TempDir;
+use tracing::{error, warn};
+
+pub fn validate(args: ValidateArgs) -> Result<()> {
+    let tmp_dir = TempDir::with_prefix(".naql-")?;
+    let dir = if args.path.is_file() {
+        unzip(&args.path, tmp_dir.path())?;
+        plugin_dir(tmp_dir.path().to_path_buf())
+    } else {
+        plugin_dir(args.path.clone())
+    };
+
+    let Some(dir) = dir else {
+        bail!("no plugin.json found in {}", args.path.to_string_lossy());
+    };
+
+    let manifest = AcodeManifest::read_from_file(join!(&dir, "plugin.json"))?;
+    report(&manifest.validate(&dir))?;
+
+    println!("{} is valid", args.path.to_string_lossy());
+    Ok(())
+}
+
+/// Logs `diagnostics`, failing if any of them is an error.
+pub fn report(diagnostics: &Diagnostics) -> Result<()> {
+    for diagnostic in diagnostics.warnings() {
+        warn!("plugin.json: {diagnostic}");
+    }
+
+    for diagnostic in diagnostics.errors() {
+        error!("plugin.json: {diagnostic}");
+    }
+
+    if diagnostics.has_errors() {
+        bail!("plugin.json has {} error(s)", diagnostics.errors().count());
+    }
+
+    Ok(())
+}
+
+/// The directory holding `plugin.json`, either `dir` itself or the `dist`
+/// directory of a naql build.
+fn plugin_dir(dir: PathBuf) -> Option<PathBuf> {
+    [join!(&dir, "dist"), dir]
+        .into_iter()
+        .rev()
+        .find(|dir| join!(dir, "plugin.json").is_file())
+}
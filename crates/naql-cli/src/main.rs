@@ -1,6 +1,6 @@
 use anyhow::{Ok, Result};
 use clap::{Parser, Subcommand};
-use naql_build::{BuildArgs, Builder};
+use naql_build::{BuildArgs, Builder, ValidateArgs, validate};
 use std::io::{IsTerminal, stdout};
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
@@ -31,7 +31,9 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Command {
     /// Build an acode plugin from vscode extension
-    Build(BuildArgs),
+    Build(Box<BuildArgs>),
+    /// Check an acode plugin against the plugin registry rules
+    Validate(ValidateArgs),
 }
 
 fn main() -> Result<()> {
@@ -49,9 +51,10 @@ fn main() -> Result<()> {
 
     match cli.command {
         Command::Build(args) => {
-            let mut builder = Builder::new(args);
+            let mut builder = Builder::new(*args);
             builder.build()?;
         }
+        Command::Validate(args) => validate(args)?,
     }
 
     Ok(())
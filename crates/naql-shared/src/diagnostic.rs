@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found in a manifest, attributed to one of its fields.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub field: String,
+    pub message: String,
+    /// Where `field` is in the source, if known
+    pub location: Option<Location>,
+}
+
+/// 1-based line and column in a source file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Default, Debug)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Diagnostics {
+    pub fn error<F: Into<String>, M: Into<String>>(
+        &mut self,
+        field: F,
+        message: M,
+        location: Option<Location>,
+    ) {
+        self.push(Severity::Error, field, message, location);
+    }
+
+    pub fn warning<F: Into<String>, M: Into<String>>(
+        &mut self,
+        field: F,
+        message: M,
+        location: Option<Location>,
+    ) {
+        self.push(Severity::Warning, field, message, location);
+    }
+
+    fn push<F: Into<String>, M: Into<String>>(
+        &mut self,
+        severity: Severity,
+        field: F,
+        message: M,
+        location: Option<Location>,
+    ) {
+        self.0.push(Diagnostic {
+            severity,
+            field: field.into(),
+            message: message.into(),
+            location,
+        });
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.0.iter().filter(|d| d.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.0.iter().filter(|d| d.severity == Severity::Warning)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(Location { line, column }) = self.location {
+            write!(f, "{line}:{column}: ")?;
+        }
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+enum Frame {
+    Object { has_key: bool },
+    Array { index: usize },
+}
+
+/// Locations of the keys of the JSON, comments allowed, in `source`, by their
+/// path from the root. Array elements are keyed by their index.
+pub fn key_locations(source: &str) -> HashMap<Vec<String>, Location> {
+    let mut locations = HashMap::new();
+    let mut path: Vec<String> = vec![];
+    let mut stack = vec![];
+    let mut expect_key = false;
+    let (mut line, mut line_start) = (1, 0);
+
+    let bytes = source.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\n' => {
+                line += 1;
+                line_start = i + 1;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i + 1 < bytes.len() && bytes[i + 1] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i + 1 < bytes.len() && !(bytes[i] == b'*' && bytes[i + 1] == b'/') {
+                    if bytes[i] == b'\n' {
+                        line += 1;
+                        line_start = i + 1;
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            b'"' => {
+                let start = i;
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+
+                if let Some(Frame::Object { has_key }) = stack.last_mut()
+                    && expect_key
+                {
+                    let raw = &source[start..(i + 1).min(source.len())];
+                    let key = serde_json::from_str::<String>(raw)
+                        .unwrap_or_else(|_| raw.trim_matches('"').to_string());
+                    path.push(key);
+                    *has_key = true;
+                    expect_key = false;
+
+                    let column = source[line_start..start].chars().count() + 1;
+                    locations.insert(path.clone(), Location { line, column });
+                }
+            }
+            b'{' => {
+                stack.push(Frame::Object { has_key: false });
+                expect_key = true;
+            }
+            b'[' => {
+                stack.push(Frame::Array { index: 0 });
+                path.push("0".to_string());
+            }
+            b',' => match stack.last_mut() {
+                Some(Frame::Object { has_key }) => {
+                    if *has_key {
+                        path.pop();
+                        *has_key = false;
+                    }
+                    expect_key = true;
+                }
+                Some(Frame::Array { index }) => {
+                    *index += 1;
+                    path.pop();
+                    path.push(index.to_string());
+                }
+                None => {}
+            },
+            b'}' | b']' => {
+                match stack.pop() {
+                    Some(Frame::Object { has_key: true }) | Some(Frame::Array { .. }) => {
+                        path.pop();
+                    }
+                    _ => {}
+                }
+                expect_key = false;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    locations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(locations: &HashMap<Vec<String>, Location>, path: &[&str]) -> Option<(usize, usize)> {
+        locations
+            .get(&path.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+            .map(|l| (l.line, l.column))
+    }
+
+    #[test]
+    fn nested_objects() {
+        let locations =
+            key_locations("{\n  \"a\": {\n    \"b\": { \"c\": 1 }\n  },\n  \"d\": 2\n}");
+        assert_eq!(at(&locations, &["a"]), Some((2, 3)));
+        assert_eq!(at(&locations, &["a", "b"]), Some((3, 5)));
+        assert_eq!(at(&locations, &["a", "b", "c"]), Some((3, 12)));
+        assert_eq!(at(&locations, &["d"]), Some((5, 3)));
+        assert_eq!(at(&locations, &["a", "d"]), None);
+    }
+
+    #[test]
+    fn arrays() {
+        let locations =
+            key_locations("{\"a\": [{\"b\": 1}, [2, {\"c\": 3}], {\"d\": 4}], \"e\": 5}");
+        assert_eq!(at(&locations, &["a"]), Some((1, 2)));
+        assert_eq!(at(&locations, &["a", "0", "b"]), Some((1, 9)));
+        assert_eq!(at(&locations, &["a", "1", "1", "c"]), Some((1, 23)));
+        assert_eq!(at(&locations, &["a", "2", "d"]), Some((1, 34)));
+        assert_eq!(at(&locations, &["e"]), Some((1, 44)));
+    }
+
+    #[test]
+    fn escapes() {
+        let locations = key_locations(r#"{"a\"b": "c\\", "é": {"x": "}"}, "y": 1}"#);
+        assert_eq!(at(&locations, &["a\"b"]), Some((1, 2)));
+        assert_eq!(at(&locations, &["é", "x"]), Some((1, 23)));
+        assert_eq!(at(&locations, &["y"]), Some((1, 34)));
+    }
+
+    #[test]
+    fn comments() {
+        let source = "{\n  // \"no\": {\n  \"a\": 1, /* \"no\": [\n  */ \"b\": /* } */ 2\n}";
+        let locations = key_locations(source);
+        assert_eq!(locations.len(), 2);
+        assert_eq!(at(&locations, &["a"]), Some((3, 3)));
+        assert_eq!(at(&locations, &["b"]), Some((4, 6)));
+    }
+}
@@ -0,0 +1,95 @@
+use crate::vfs::Vfs;
+use anyhow::{Context, Result, anyhow};
+use image::imageops::{FilterType, overlay};
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::{Options, Tree};
+use std::path::Path;
+
+/// Width and height of the plugin icon expected by Acode.
+pub const ICON_SIZE: u32 = 96;
+
+/// Icon used when the extension has none.
+const DEFAULT_ICON: &[u8] = include_bytes!("../assets/icon.svg");
+
+/// Decodes the image at `src`, rasterising SVGs, and writes it to `dest` as a
+/// `size`x`size` PNG. Non-square images are centered on a transparent canvas.
+pub fn write_png<P1: AsRef<Path>, P2: AsRef<Path>>(
+    vfs: &Vfs,
+    src: P1,
+    dest: P2,
+    size: u32,
+) -> Result<()> {
+    let src = src.as_ref();
+    let data = vfs
+        .read(src)
+        .with_context(|| format!("icon: cannot read {}", src.to_string_lossy()))?;
+
+    let image = if is_svg(src, &data) {
+        rasterise(&data, size)?
+    } else {
+        let image = image::load_from_memory(&data)
+            .with_context(|| format!("icon: cannot decode {}", src.to_string_lossy()))?;
+        fit(image, size)
+    };
+
+    image.save_with_format(dest, ImageFormat::Png)?;
+    Ok(())
+}
+
+/// Writes the default icon to `dest` as a `size`x`size` PNG.
+pub fn write_default_png<P: AsRef<Path>>(dest: P, size: u32) -> Result<()> {
+    rasterise(DEFAULT_ICON, size)?.save_with_format(dest, ImageFormat::Png)?;
+    Ok(())
+}
+
+fn is_svg(path: &Path, data: &[u8]) -> bool {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    matches!(ext.as_deref(), Some("svg" | "svgz"))
+        || (image::guess_format(data).is_err()
+            && String::from_utf8_lossy(&data[..data.len().min(1024)]).contains("<svg"))
+}
+
+fn rasterise(data: &[u8], size: u32) -> Result<RgbaImage> {
+    let tree = Tree::from_data(data, &Options::default())?;
+    let svg = tree.size();
+    let scale = size as f32 / svg.width().max(svg.height());
+    let x = (size as f32 - svg.width() * scale) / 2.0;
+    let y = (size as f32 - svg.height() * scale) / 2.0;
+
+    let mut pixmap = Pixmap::new(size, size).ok_or_else(|| anyhow!("icon: invalid size {size}"))?;
+    resvg::render(
+        &tree,
+        Transform::from_scale(scale, scale).post_translate(x, y),
+        &mut pixmap.as_mut(),
+    );
+
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+
+    RgbaImage::from_raw(size, size, pixels).ok_or_else(|| anyhow!("icon: invalid size {size}"))
+}
+
+fn fit(image: DynamicImage, size: u32) -> RgbaImage {
+    if image.width() == size && image.height() == size {
+        return image.into_rgba8();
+    }
+
+    let resized = image.resize(size, size, FilterType::Lanczos3).into_rgba8();
+    let mut canvas = RgbaImage::new(size, size);
+    overlay(
+        &mut canvas,
+        &resized,
+        ((size - resized.width()) / 2).into(),
+        ((size - resized.height()) / 2).into(),
+    );
+    canvas
+}
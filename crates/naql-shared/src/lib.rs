@@ -1,6 +1,11 @@
+pub mod diagnostic;
+pub mod icon;
 pub mod manifest;
 pub mod node;
+pub mod readme;
+pub mod repository;
 pub mod traits;
+pub mod vfs;
 pub mod zip;
 
 #[macro_export]
@@ -1,10 +1,61 @@
-use super::vscode::VsCodeManifest;
-use crate::{join, ok, own, path, traits::WriteToFile};
+use super::vscode::{Author as VsCodeAuthor, VsCodeManifest};
+use super::vsix::{self, VsixManifest};
+use crate::diagnostic::Diagnostics;
+use crate::icon::{ICON_SIZE, write_default_png, write_png};
+use crate::readme::{IMAGES_DIR, Readme};
+use crate::traits::{ReadFromFile, WriteToFile};
+use crate::vfs::Vfs;
+use crate::{join, ok, own};
+use anyhow::{Context, anyhow, bail};
+use glob::{MatchOptions, Pattern};
+use semver::Version;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::{
-    fs::copy,
+    collections::{HashMap, HashSet},
+    fs::{File, copy, create_dir_all, write},
+    io::{self, Read},
+    mem::take,
     path::{Path, PathBuf},
 };
+use tracing::warn;
+use walkdir::WalkDir;
+
+/// README file names looked up in the extension, in order of preference
+const README_NAMES: [&str; 4] = ["readme.md", "readme.markdown", "readme.txt", "readme"];
+
+/// Changelog file names looked up in the extension, in order of preference
+const CHANGELOG_NAMES: [&str; 4] = [
+    "changelog.md",
+    "changelog.markdown",
+    "changelog.txt",
+    "changelog",
+];
+
+/// Phrases of licences that don't allow redistribution
+const RESTRICTIVE: [&str; 4] = [
+    "all rights reserved",
+    "proprietary",
+    "not be redistributed",
+    "not redistribute",
+];
+
+/// Phrases of licences that allow redistribution, some of which also reserve
+/// all rights
+const PERMISSIVE: [&str; 7] = [
+    "permission is hereby granted",
+    "redistribution and use",
+    "apache license",
+    "general public license",
+    "mozilla public license",
+    "this is free and unencumbered software",
+    "creative commons",
+];
+
+/// Fields of an override manifest holding a path, besides `files`
+pub const PATH_FIELDS: [&str; 4] = ["main", "readme", "icon", "changelogs"];
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
 
 /// Plugin.json is a manifest file that contains information about the plugin,
 /// such as name, description, author, etc. It is required for every plugin.
@@ -15,15 +66,17 @@ pub struct AcodeManifest {
     pub id: Option<String>,
     /// Name of the plugin
     pub name: Option<String>,
-    /// Path to the main.js file
+    /// Path to the main.js file, an override manifest may point it at a custom
+    /// entry point bundled in place of the generated one
     pub main: Option<String>,
     /// Version of the plugin
     pub version: Option<String>,
     /// Path to the readme.md file
     pub readme: Option<PathBuf>,
-    /// Path to the icon.png file
+    /// Path to the icon.png file, any image or SVG is converted to a PNG of
+    /// [`ICON_SIZE`] when bundled
     pub icon: Option<PathBuf>,
-    /// List of files to be included in the plugin zip file
+    /// List of files to be included in the plugin zip file, as globs
     pub files: Option<Vec<String>>,
     /// Price of the plugin in INR (min. 10 and max. 10000), if 0 or omitted, plugin is free, this can be changed later.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -34,6 +87,22 @@ pub struct AcodeManifest {
     pub author: Option<Author>,
     /// Dependencies
     pub dependencies: Option<Vec<String>>,
+    /// Keywords the plugin is found by in the registry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keywords: Option<Vec<String>>,
+    /// Path to the changelog
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changelogs: Option<PathBuf>,
+    /// SPDX identifier of the licence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// Licence texts, added to `files` when bundled
+    #[serde(skip)]
+    pub license_files: Vec<PathBuf>,
+    /// Directory of the override manifest, its `files` keep their path
+    /// relative to it when bundled
+    #[serde(skip)]
+    pub override_dir: Option<PathBuf>,
 }
 
 #[derive(Clone, Deserialize, Serialize)]
@@ -51,51 +120,218 @@ pub struct Author {
     pub github: Option<String>,
 }
 impl AcodeManifest {
-    pub fn merge(&mut self, with: Self) {
-        macro_rules! merge {
-            ( $f:tt ) => {
-                if with.$f.is_some() {
-                    self.$f = with.$f
-                }
-            };
+    /// Reads the override manifest at `path` for [`Self::merge`], resolving
+    /// its paths, including `files`, relative to it.
+    pub fn read_override<P: AsRef<Path>>(path: P) -> anyhow::Result<Value> {
+        let path = path.as_ref();
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("cannot read manifest {}", path.to_string_lossy()))?;
+        let mut value = Value::read_from_file(&path)
+            .with_context(|| format!("invalid manifest {}", path.to_string_lossy()))?;
+        let base = ok!(path.parent());
+
+        let Value::Object(fields) = &mut value else {
+            bail!("invalid manifest {}: not an object", path.to_string_lossy());
+        };
+
+        let resolve = |field: &str, p: &mut String| {
+            let resolved = join!(base, &p);
+            if !resolved.exists() {
+                bail!("{field}: {p} does not exist, in {}", path.to_string_lossy());
+            }
+            *p = resolved.to_string_lossy().into_owned();
+            Ok(())
+        };
+
+        for field in PATH_FIELDS {
+            if let Some(Value::String(p)) = fields.get_mut(field) {
+                resolve(field, p)?;
+            }
+        }
 
-            ( $f:tt, $v:expr ) => {
-                let val = self.$f.get_or_insert($v);
-                if let Some(v) = &with.$f {
-                    val.extend_from_slice(v);
+        // globs match files of the plugin rather than of the override
+        if let Some(Value::Array(files)) = fields.get_mut("files") {
+            for file in files {
+                if let Value::String(p) = file
+                    && !p.contains(['*', '?', '['])
+                {
+                    resolve("files", p)?;
                 }
-            };
+            }
         }
 
-        merge!(id);
-        merge!(name);
-        // merge!(main); ignore main
-        merge!(version);
-        merge!(readme);
-        merge!(icon);
-        merge!(price);
-        merge!(min_version_code);
-        merge!(author);
-        merge!(files, vec![]);
-        merge!(dependencies, vec![]);
+        Ok(value)
     }
 
-    pub fn resolve<P: AsRef<Path>>(&mut self, path: P) {
-        if let Some(readme) = &self.readme {
-            self.readme = Some(join!(&path, readme))
+    /// Deep-merges the override manifest `with` into this one. Objects are
+    /// merged field by field, lists are extended without duplicates and
+    /// `null` removes the inherited value.
+    pub fn merge(&mut self, with: Value) -> anyhow::Result<()> {
+        let mut merged = serde_json::to_value(&*self)?;
+        merge_value(&mut merged, with);
+
+        let license_files = take(&mut self.license_files);
+        let override_dir = take(&mut self.override_dir);
+        *self = serde_json::from_value(merged)?;
+        self.license_files = license_files;
+        self.override_dir = override_dir;
+
+        Ok(())
+    }
+
+    /// Fills in a missing `readme`, `icon`, `changelogs` or licence text from
+    /// the extension in `vfs`, preferring the assets listed in its
+    /// vsixmanifest.
+    pub fn discover(&mut self, vfs: &Vfs, vsix: Option<&VsixManifest>) {
+        if !self.readme.as_ref().is_some_and(|p| vfs.is_file(p)) {
+            self.readme = vsix
+                .and_then(|v| v.asset(vsix::DETAILS))
+                .or_else(|| find_file(vfs, &README_NAMES));
+        }
+
+        if !self.icon.as_ref().is_some_and(|p| vfs.is_file(p)) {
+            self.icon = vsix.and_then(|v| v.asset(vsix::ICON));
+        }
+
+        if !self.changelogs.as_ref().is_some_and(|p| vfs.is_file(p)) {
+            self.changelogs = vsix
+                .and_then(|v| v.asset(vsix::CHANGELOG))
+                .or_else(|| find_file(vfs, &CHANGELOG_NAMES));
         }
 
-        if let Some(icon) = &self.icon {
-            self.icon = Some(join!(&path, icon))
+        // npm's way of pointing at a custom licence
+        if let Some(file) = self
+            .license
+            .as_deref()
+            .and_then(|l| l.strip_prefix("SEE LICENSE IN "))
+        {
+            let path = PathBuf::from(file.trim());
+            if vfs.is_file(&path) {
+                self.license_files.push(path);
+            } else {
+                warn!("license: {} does not exist", path.to_string_lossy());
+            }
+            self.license = None;
+        }
+
+        if self.license_files.is_empty() {
+            self.license_files = match vsix.and_then(|v| v.asset(vsix::LICENSE)) {
+                Some(path) => vec![path],
+                None => find_licenses(vfs),
+            };
         }
     }
 
-    pub fn bundle<P: AsRef<Path>>(&mut self, dest: P) -> anyhow::Result<()> {
+    /// Writes the README, icon, changelog, licence texts and `plugin.json` to
+    /// `dest`, reading them from `vfs`. `origin` is the extension the plugin is
+    /// ported from.
+    pub fn bundle<P: AsRef<Path>>(
+        &mut self,
+        vfs: &Vfs,
+        dest: P,
+        origin: &VsCodeManifest,
+    ) -> anyhow::Result<()> {
         let readme = PathBuf::from("readme.md");
         let icon = PathBuf::from("icon.png");
+        let changelogs = PathBuf::from("changelogs.md");
+
+        // files of an override manifest, resolved to absolute paths, are
+        // copied relative to it or by name if outside of it
+        let mut copied = HashMap::new();
+        for file in self.files.iter_mut().flatten() {
+            let src = PathBuf::from(&*file);
+            if !src.is_absolute() {
+                continue;
+            }
+            let src = src.canonicalize()?;
 
-        copy(ok!(self.readme.as_ref()), join!(&dest, &readme))?;
-        copy(ok!(self.icon.as_ref()), join!(&dest, &icon))?;
+            let path = self
+                .override_dir
+                .as_ref()
+                .and_then(|dir| src.strip_prefix(dir).ok())
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| own!(ok!(src.file_name())).into());
+            if let Some(other) = copied.insert(path.clone(), src.clone())
+                && other != src
+            {
+                bail!(
+                    "files: {} and {} are both bundled as {}",
+                    other.to_string_lossy(),
+                    src.to_string_lossy(),
+                    path.to_string_lossy()
+                );
+            }
+
+            let target = join!(&dest, &path);
+            if let Some(parent) = target.parent() {
+                create_dir_all(parent)?;
+            }
+            copy_all(&src, &target)?;
+            *file = path.to_string_lossy().into_owned();
+        }
+        if let Some(files) = &mut self.files {
+            let mut seen = HashSet::new();
+            files.retain(|f| seen.insert(f.clone()));
+        }
+
+        let src = self
+            .readme
+            .as_ref()
+            .ok_or_else(|| anyhow!("readme: missing"))?;
+        let repository = origin.repository.as_ref().and_then(|r| r.parse());
+        let rewriter = Readme {
+            vfs,
+            base: src.parent().unwrap_or(Path::new("")),
+            dest: dest.as_ref(),
+            repository: repository.as_ref(),
+            origin: &origin.id(),
+        };
+        write(
+            join!(&dest, &readme),
+            rewriter.rewrite(&vfs.read_to_string(src)?),
+        )?;
+        if join!(&dest, IMAGES_DIR).is_dir() {
+            self.files.get_or_insert_default().push(own!(IMAGES_DIR));
+        }
+
+        match &self.icon {
+            Some(src) if vfs.is_file(src) => write_png(vfs, src, join!(&dest, &icon), ICON_SIZE)?,
+            src => {
+                if let Some(src) = src {
+                    warn!(
+                        "icon: {} does not exist, using the default icon",
+                        src.to_string_lossy()
+                    );
+                }
+                write_default_png(join!(&dest, &icon), ICON_SIZE)?
+            }
+        }
+
+        if let Some(src) = &self.changelogs {
+            vfs.copy(src, join!(&dest, &changelogs))?;
+            self.changelogs = Some(changelogs);
+        }
+
+        let mut texts = vec![];
+        for src in &self.license_files {
+            let name = ok!(src.file_name()).to_string_lossy().into_owned();
+            vfs.copy(src, join!(&dest, &name))?;
+            texts.push(vfs.read_to_string(src).unwrap_or_default());
+            self.files.get_or_insert_default().push(name);
+        }
+
+        if self.license.is_none() && texts.is_empty() {
+            warn!(
+                "license: {} has no licence, it may not be redistributable",
+                origin.id()
+            );
+        } else if forbids_redistribution(self.license.as_deref(), &texts) {
+            warn!(
+                "license: the licence of {} may forbid redistribution",
+                origin.id()
+            );
+        }
 
         self.readme = Some(readme);
         self.icon = Some(icon);
@@ -104,25 +340,358 @@ impl AcodeManifest {
     }
 }
 
+impl AcodeManifest {
+    /// Paths, relative to `dir`, making up the plugin: `plugin.json`, the
+    /// files it points at and whatever the `files` globs match. Directories
+    /// stand for everything under them.
+    pub fn payload<P: AsRef<Path>>(&self, dir: P) -> anyhow::Result<Vec<PathBuf>> {
+        let dir = dir.as_ref();
+        let mut payload = vec![PathBuf::from("plugin.json")];
+        payload.extend(self.main.iter().map(PathBuf::from));
+        payload.extend(self.readme.iter().cloned());
+        payload.extend(self.icon.iter().cloned());
+        payload.extend(self.changelogs.iter().cloned());
+
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        let paths = WalkDir::new(dir)
+            .min_depth(1)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .map(|e| own!(ok!(e.path().strip_prefix(dir))))
+            .collect::<Vec<_>>();
+
+        for pattern in self.files.iter().flatten() {
+            let glob = Pattern::new(pattern)
+                .with_context(|| format!("files: invalid pattern {pattern}"))?;
+            payload.extend(
+                paths
+                    .iter()
+                    .filter(|p| glob.matches_path_with(p, options))
+                    .cloned(),
+            );
+        }
+
+        payload.sort();
+        payload.dedup();
+        Ok(payload)
+    }
+
+    /// Checks the manifest against the rules of the Acode plugin registry.
+    /// Paths are checked relative to `dir`, the directory holding
+    /// `plugin.json`.
+    pub fn validate<P: AsRef<Path>>(&self, dir: P) -> Diagnostics {
+        let dir = dir.as_ref();
+        let mut diagnostics = Diagnostics::default();
+
+        match &self.id {
+            None => diagnostics.error("id", "missing", None),
+            Some(id) if !is_plugin_id(id) => diagnostics.error(
+                "id",
+                format!("`{id}` is not in reverse domain name format, e.g. `publisher.plugin`"),
+                None,
+            ),
+            Some(id) if id.chars().any(|c| c.is_ascii_uppercase()) => {
+                diagnostics.warning("id", format!("`{id}` should be lowercase"), None)
+            }
+            _ => {}
+        }
+
+        match &self.name {
+            Some(name) if !name.trim().is_empty() => {}
+            _ => diagnostics.error("name", "missing", None),
+        }
+
+        match &self.version {
+            None => diagnostics.error("version", "missing", None),
+            Some(version) => {
+                if let Err(e) = Version::parse(version) {
+                    diagnostics.error(
+                        "version",
+                        format!("`{version}` is not a semver version: {e}"),
+                        None,
+                    )
+                }
+            }
+        }
+
+        match &self.main {
+            None => diagnostics.error("main", "missing", None),
+            Some(main) if !join!(dir, main).is_file() => {
+                diagnostics.error("main", format!("{main} does not exist"), None)
+            }
+            _ => {}
+        }
+
+        match &self.readme {
+            None => diagnostics.error("readme", "missing", None),
+            Some(readme) if !join!(dir, readme).is_file() => diagnostics.error(
+                "readme",
+                format!("{} does not exist", readme.to_string_lossy()),
+                None,
+            ),
+            _ => {}
+        }
+
+        match &self.icon {
+            None => diagnostics.error("icon", "missing", None),
+            Some(icon) => {
+                let path = join!(dir, icon);
+                // signature and IHDR chunk
+                let mut header = [0; 24];
+                match File::open(&path).and_then(|mut f| f.read_exact(&mut header)) {
+                    Err(_) if !path.is_file() => diagnostics.error(
+                        "icon",
+                        format!("{} does not exist", icon.to_string_lossy()),
+                        None,
+                    ),
+                    Ok(()) if header[..8] == PNG_SIGNATURE => {
+                        let width = u32::from_be_bytes(ok!(header[16..20].try_into()));
+                        let height = u32::from_be_bytes(ok!(header[20..24].try_into()));
+                        if (width, height) != (ICON_SIZE, ICON_SIZE) {
+                            diagnostics.warning(
+                                "icon",
+                                format!("{width}x{height}, expected {ICON_SIZE}x{ICON_SIZE}"),
+                                None,
+                            );
+                        }
+                    }
+                    _ => diagnostics.error(
+                        "icon",
+                        format!("{} is not a PNG image", icon.to_string_lossy()),
+                        None,
+                    ),
+                }
+            }
+        }
+
+        if let Some(changelogs) = &self.changelogs
+            && !join!(dir, changelogs).is_file()
+        {
+            diagnostics.error(
+                "changelogs",
+                format!("{} does not exist", changelogs.to_string_lossy()),
+                None,
+            );
+        }
+
+        if let Some(files) = &self.files {
+            for file in files {
+                let pattern = join!(dir, file).to_string_lossy().into_owned();
+                let matched = glob::glob(&pattern).is_ok_and(|mut paths| paths.next().is_some());
+                if !matched {
+                    diagnostics.warning("files", format!("{file} does not exist"), None);
+                }
+            }
+        }
+
+        if let Some(price) = self.price
+            && price != 0
+            && !(10..=10000).contains(&price)
+        {
+            diagnostics.error(
+                "price",
+                format!("{price} is outside 10..=10000, use 0 for free plugins"),
+                None,
+            );
+        }
+
+        match self.min_version_code {
+            None => diagnostics.warning(
+                "minVersionCode",
+                "missing, the plugin may be installed on unsupported Acode versions",
+                None,
+            ),
+            Some(code) if code < 0 => {
+                diagnostics.error("minVersionCode", format!("{code} is negative"), None)
+            }
+            _ => {}
+        }
+
+        match &self.author {
+            None => diagnostics.warning("author", "missing", None),
+            Some(author) => {
+                if author.name.trim().is_empty() {
+                    diagnostics.error("author.name", "missing", None);
+                }
+                if let Some(email) = &author.email
+                    && !email.contains('@')
+                {
+                    diagnostics.warning(
+                        "author.email",
+                        format!("`{email}` is not an email address"),
+                        None,
+                    );
+                }
+            }
+        }
+
+        if let Some(dependencies) = &self.dependencies {
+            for dependency in dependencies {
+                if !is_plugin_id(dependency) {
+                    diagnostics.error(
+                        "dependencies",
+                        format!("`{dependency}` is not a plugin id"),
+                        None,
+                    );
+                }
+            }
+        }
+
+        diagnostics
+    }
+}
+
+fn merge_value(base: &mut Value, with: Value) {
+    match (base, with) {
+        (Value::Object(base), Value::Object(with)) => {
+            for (key, value) in with {
+                if value.is_null() {
+                    base.remove(&key);
+                } else {
+                    merge_value(base.entry(key).or_insert(Value::Null), value);
+                }
+            }
+        }
+        (Value::Array(base), Value::Array(with)) => {
+            for value in with {
+                if !base.contains(&value) {
+                    base.push(value);
+                }
+            }
+        }
+        (base, with) => *base = with,
+    }
+}
+
+/// Copies the file or directory `src` to `dest`.
+fn copy_all(src: &Path, dest: &Path) -> io::Result<()> {
+    for entry in WalkDir::new(src) {
+        let entry = entry?;
+        let path = ok!(entry.path().strip_prefix(src));
+        // `src` itself, when it is a file
+        let target = if path.as_os_str().is_empty() {
+            dest.to_path_buf()
+        } else {
+            join!(dest, path)
+        };
+        if entry.file_type().is_dir() {
+            create_dir_all(target)?;
+        } else {
+            copy(entry.path(), target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds the first of `names` at the root of `vfs`, ignoring case.
+fn find_file(vfs: &Vfs, names: &[&str]) -> Option<PathBuf> {
+    let files = vfs.read_dir("").ok()?;
+
+    names.iter().find_map(|name| {
+        files
+            .iter()
+            .find(|p| file_name(p).eq_ignore_ascii_case(name))
+            .cloned()
+    })
+}
+
+/// Licence texts at the root of `vfs`, `LICENSE*` or `LICENCE*` ignoring case.
+fn find_licenses(vfs: &Vfs) -> Vec<PathBuf> {
+    let Ok(files) = vfs.read_dir("") else {
+        return vec![];
+    };
+
+    let mut licenses = files
+        .into_iter()
+        .filter(|p| {
+            let name = file_name(p).to_ascii_lowercase();
+            name.starts_with("license") || name.starts_with("licence")
+        })
+        .collect::<Vec<_>>();
+    licenses.sort();
+    licenses
+}
+
+fn file_name(path: &Path) -> std::borrow::Cow<'_, str> {
+    path.file_name().unwrap_or_default().to_string_lossy()
+}
+
+fn forbids_redistribution(license: Option<&str>, texts: &[String]) -> bool {
+    // npm's identifier for proprietary packages
+    if license.is_some_and(|l| l.eq_ignore_ascii_case("UNLICENSED")) {
+        return true;
+    }
+
+    texts.iter().any(|text| {
+        let text = text.to_lowercase();
+        RESTRICTIVE.iter().any(|p| text.contains(p)) && !PERMISSIVE.iter().any(|p| text.contains(p))
+    })
+}
+
+fn is_plugin_id(id: &str) -> bool {
+    let mut segments = id.split('.');
+    segments.clone().count() >= 2
+        && segments.all(|s| {
+            !s.is_empty()
+                && s.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        })
+}
+
 impl From<VsCodeManifest> for AcodeManifest {
     fn from(manifest: VsCodeManifest) -> Self {
+        let repository = manifest.repository.as_ref().and_then(|r| r.parse());
+        // the publisher stands in for a missing author
+        let author = manifest.author.clone().unwrap_or_else(|| VsCodeAuthor {
+            name: own!(manifest.publisher()),
+            email: None,
+            url: None,
+        });
+        let keywords =
+            manifest
+                .keywords
+                .iter()
+                .chain(&manifest.categories)
+                .fold(vec![], |mut keywords, k| {
+                    if !keywords.contains(k) {
+                        keywords.push(k.clone());
+                    }
+                    keywords
+                });
+
         Self {
             id: Some(manifest.id()),
-            name: Some(manifest.display_name),
+            name: Some(own!(manifest.display_name())),
             main: Some(own!("main.js")),
             version: Some(manifest.version),
-            readme: Some(path!("README.md")),
-            icon: Some(manifest.icon),
+            readme: None,
+            icon: manifest.icon,
             files: None,
             price: None,
-            min_version_code: Some(955),
+            // filled in by the build, from the Acode features the plugin uses
+            min_version_code: None,
             author: Some(Author {
-                name: manifest.author.name,
-                email: manifest.author.email,
-                url: manifest.author.url,
-                github: None,
+                name: author.name,
+                email: author.email,
+                url: author
+                    .url
+                    .or(manifest.homepage)
+                    .or_else(|| repository.as_ref().map(|r| r.web_url())),
+                github: repository
+                    .as_ref()
+                    .and_then(|r| r.github())
+                    .map(|g| own!(g)),
             }),
             dependencies: Some(vec![own!("almukaafih.vscode_api")]),
+            changelogs: None,
+            license: manifest.license,
+            keywords: (!keywords.is_empty()).then_some(keywords),
+            license_files: vec![],
+            override_dir: None,
         }
     }
 }
@@ -1,2 +1,3 @@
 pub mod acode;
 pub mod vscode;
+pub mod vsix;
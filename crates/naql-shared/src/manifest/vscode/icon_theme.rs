@@ -1,4 +1,7 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
 
 use crate::own;
 use serde::{Deserialize, Serialize, ser::SerializeStruct};
@@ -78,8 +81,8 @@ macro_rules! skip_if_none {
     };
 
     ( $state:expr , $key:expr, $value:expr, $map:expr ) => {
-        if $value.is_some() {
-            let new_map = IconThemeManifest::map(&$value.as_ref().unwrap(), &$map);
+        if let Some(value) = &$value {
+            let new_map = IconThemeManifest::map(value, &$map);
             $state.serialize_field($key, &new_map)?;
         }
     };
@@ -92,15 +95,49 @@ impl IconThemeManifest {
     fn split_icon_defs(&self) -> (Map, DefsMap) {
         let mut defsmap = DefsMap::with_capacity(self.icon_definitions.len());
         let mut map = HashMap::with_capacity(self.icon_definitions.len());
-        let mut i = 0;
-        for (k, v) in &self.icon_definitions {
+        for (i, (k, v)) in (0..).zip(&self.icon_definitions) {
             map.insert(k.clone(), i);
             defsmap.insert(i, v.clone());
-            i += 1;
         }
         (map, defsmap)
     }
 
+    /// Ids of the icon definitions the theme maps something to, in the
+    /// default, `light` and `highContrast` sections.
+    pub fn references(&self) -> HashSet<&str> {
+        let mut references = HashSet::new();
+        let defaults = [
+            &self.file,
+            &self.folder,
+            &self.folder_expanded,
+            &self.root_folder,
+            &self.root_folder_expanded,
+        ];
+        references.extend(defaults.into_iter().flatten().map(|s| s.as_str()));
+
+        let mappings = [
+            &self.folder_names,
+            &self.folder_names_expanded,
+            &self.root_folder_names,
+            &self.root_folder_names_expanded,
+            &self.language_ids,
+            &self.file_extensions,
+            &self.file_names,
+        ];
+        for mapping in mappings.into_iter().flatten() {
+            references.extend(mapping.values().map(|s| s.as_str()));
+        }
+
+        // same keys as the default section
+        for section in [&self.light, &self.high_contrast].into_iter().flatten() {
+            for value in section.values() {
+                collect_strings(value, &mut references);
+            }
+        }
+
+        references
+    }
+
     // Maps HashMap<String, String> -> HashMap<String, i32>
     fn map(from: &HashMap<String, String>, to: &HashMap<String, i32>) -> Map {
         from.iter()
@@ -114,7 +151,7 @@ impl Serialize for IconThemeManifest {
     where
         S: serde::Serializer,
     {
-        let (map, defsmap) = Self::split_icon_defs(&self);
+        let (map, defsmap) = Self::split_icon_defs(self);
         let mut state = serializer.serialize_struct("IconThemeManifest", 5)?;
         state.serialize_field("0", &defsmap)?;
         skip_if_none!(state, "1", self.file_extensions, map);
@@ -123,3 +160,13 @@ impl Serialize for IconThemeManifest {
         state.end()
     }
 }
+
+fn collect_strings<'a>(value: &'a Value, strings: &mut HashSet<&'a str>) {
+    match value {
+        Value::String(s) => {
+            strings.insert(s);
+        }
+        Value::Object(map) => map.values().for_each(|v| collect_strings(v, strings)),
+        _ => {}
+    }
+}
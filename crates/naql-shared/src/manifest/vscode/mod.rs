@@ -1,4 +1,4 @@
-use crate::own;
+use crate::{own, repository};
 use anyhow::Result;
 use serde::de::{self, MapAccess, Visitor};
 use serde::{Deserialize, Deserializer};
@@ -6,22 +6,62 @@ use std::fmt;
 use std::marker::PhantomData;
 use std::path::PathBuf;
 use std::str::FromStr;
+use tracing::debug;
 use void::Void;
 
 pub mod icon_theme;
 
+/// Publisher VS Code assigns to extensions that have none
+pub const UNDEFINED_PUBLISHER: &str = "undefined_publisher";
+
+/// The `package.json` of an extension. Only `name` and `version` are required,
+/// everything else falls back to a default.
 #[derive(Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct VsCodeManifest {
     pub name: String,
-    pub display_name: String,
-    pub description: String,
     pub version: String,
-    pub publisher: String,
-    #[serde(deserialize_with = "string_or_struct")]
-    pub author: Author,
+    pub publisher: Option<String>,
+    pub display_name: Option<String>,
+    pub description: Option<String>,
+    #[serde(default, deserialize_with = "option_string_or_struct")]
+    pub author: Option<Author>,
+    #[serde(default)]
     pub contributes: Contributes,
-    pub icon: PathBuf,
+    pub icon: Option<PathBuf>,
+    #[serde(default)]
+    pub engines: Engines,
+    #[serde(default)]
+    pub categories: Vec<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    pub repository: Option<Repository>,
+    #[serde(default, deserialize_with = "option_string_or_struct")]
+    pub bugs: Option<Bugs>,
+    pub homepage: Option<String>,
+    pub license: Option<String>,
+    /// Entry point for desktop VS Code
+    pub main: Option<String>,
+    /// Entry point for VS Code for the Web
+    pub browser: Option<String>,
+    #[serde(default)]
+    pub activation_events: Vec<String>,
+    #[serde(default)]
+    pub extension_dependencies: Vec<String>,
+    #[serde(default)]
+    pub extension_pack: Vec<String>,
+    #[serde(default)]
+    pub capabilities: Capabilities,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Repository {
+    Url(String),
+    Object {
+        url: String,
+        directory: Option<String>,
+    },
 }
 
 #[derive(Deserialize, Clone)]
@@ -31,12 +71,50 @@ pub struct Author {
     pub url: Option<String>,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, Default)]
+pub struct Bugs {
+    pub url: Option<String>,
+    pub email: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct Engines {
+    /// Semver range of the supported VS Code versions
+    pub vscode: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Contributes {
     pub icon_themes: Option<Vec<Theme>>,
 }
 
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    pub untrusted_workspaces: Option<Support>,
+    pub virtual_workspaces: Option<Support>,
+}
+
+/// Whether the extension works in untrusted or virtual workspaces.
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Support {
+    Bool(bool),
+    Object {
+        supported: Supported,
+        description: Option<String>,
+    },
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Supported {
+    Bool(bool),
+    /// `"limited"`
+    Limited(String),
+}
+
 #[derive(Deserialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Theme {
@@ -47,7 +125,59 @@ pub struct Theme {
 
 impl VsCodeManifest {
     pub fn id(&self) -> String {
-        format!("{}.{}", self.publisher, self.name)
+        format!("{}.{}", self.publisher(), self.name)
+    }
+
+    pub fn publisher(&self) -> &str {
+        self.publisher.as_deref().unwrap_or(UNDEFINED_PUBLISHER)
+    }
+
+    /// Ids of the extensions this one depends on or bundles as a pack.
+    pub fn dependencies(&self) -> impl Iterator<Item = &String> {
+        self.extension_dependencies
+            .iter()
+            .chain(&self.extension_pack)
+    }
+
+    /// Name shown to users, `displayName` or else `name`.
+    pub fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.name)
+    }
+
+    /// README used when the extension doesn't ship one.
+    pub fn placeholder_readme(&self) -> String {
+        let mut readme = format!("# {}\n", self.display_name());
+        if let Some(description) = &self.description {
+            readme.push_str(&format!("\n{description}\n"));
+        }
+
+        if let Some(themes) = &self.contributes.icon_themes {
+            readme.push_str("\n## Icon Themes\n\n");
+            for theme in themes {
+                readme.push_str(&format!("- {}\n", theme.label));
+            }
+        }
+
+        readme
+    }
+}
+
+impl Repository {
+    /// Parses the repository, `None` if it isn't hosted anywhere naql knows
+    /// how to link to.
+    pub fn parse(&self) -> Option<repository::Repository> {
+        let (url, directory) = match self {
+            Self::Url(url) => (url, None),
+            Self::Object { url, directory } => (url, directory.as_deref()),
+        };
+
+        match url.parse::<repository::Repository>() {
+            Ok(repository) => Some(repository.with_directory(directory)),
+            Err(e) => {
+                debug!("repository: {e}");
+                None
+            }
+        }
     }
 }
 
@@ -103,6 +233,29 @@ impl FromStr for Author {
     }
 }
 
+impl FromStr for Bugs {
+    type Err = Void;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Bugs {
+            url: Some(own!(s.trim())),
+            email: None,
+        })
+    }
+}
+
+fn option_string_or_struct<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de> + FromStr<Err = Void>,
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(bound = "T: Deserialize<'de> + FromStr<Err = Void>")]
+    struct Wrapper<T>(#[serde(deserialize_with = "string_or_struct")] T);
+
+    Ok(Option::<Wrapper<T>>::deserialize(deserializer)?.map(|Wrapper(v)| v))
+}
+
 fn string_or_struct<'de, T, D>(deserializer: D) -> Result<T, D::Error>
 where
     T: Deserialize<'de> + FromStr<Err = Void>,
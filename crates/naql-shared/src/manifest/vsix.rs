@@ -0,0 +1,65 @@
+use crate::join;
+use crate::vfs::Vfs;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+pub const VSIX_MANIFEST: &str = "extension.vsixmanifest";
+
+pub const DETAILS: &str = "Microsoft.VisualStudio.Services.Content.Details";
+pub const CHANGELOG: &str = "Microsoft.VisualStudio.Services.Content.Changelog";
+pub const LICENSE: &str = "Microsoft.VisualStudio.Services.Content.License";
+pub const ICON: &str = "Microsoft.VisualStudio.Services.Icons.Default";
+
+/// The `extension.vsixmanifest` at the root of a .vsix, listing the assets
+/// the marketplace shows for the extension.
+pub struct VsixManifest {
+    vfs: Vfs,
+    assets: Vec<(String, PathBuf)>,
+}
+
+impl VsixManifest {
+    /// Reads the vsixmanifest next to the `extension` directory at the root of
+    /// `vfs`, if there is one.
+    pub fn find(vfs: &Vfs) -> Result<Option<Self>> {
+        let path = join!("..", VSIX_MANIFEST);
+        if !vfs.is_file(&path) {
+            return Ok(None);
+        }
+
+        let xml = vfs.read_to_string(path)?;
+        let doc = roxmltree::Document::parse(&xml)?;
+        let assets = doc
+            .descendants()
+            .filter(|n| n.has_tag_name("Asset"))
+            .filter_map(|n| {
+                Some((
+                    n.attribute("Type")?.to_owned(),
+                    PathBuf::from(n.attribute("Path")?),
+                ))
+            })
+            .collect();
+
+        Ok(Some(Self {
+            vfs: vfs.clone(),
+            assets,
+        }))
+    }
+
+    /// Path of the asset of type `r#type` in the vfs, if it exists.
+    pub fn asset(&self, r#type: &str) -> Option<PathBuf> {
+        self.assets
+            .iter()
+            .find(|(t, _)| t == r#type)
+            .map(|(_, path)| asset_path(path))
+            .filter(|path| self.vfs.is_file(path))
+    }
+}
+
+/// Asset paths are relative to the root of the .vsix, the vfs is rooted at the
+/// `extension` directory in it.
+fn asset_path(path: &Path) -> PathBuf {
+    match path.strip_prefix("extension") {
+        Ok(path) => path.to_path_buf(),
+        Err(_) => join!("..", path),
+    }
+}
@@ -4,16 +4,14 @@ use std::{env::current_dir, path::PathBuf};
 use which::which;
 
 pub fn find_binary(binary_name: &str) -> Result<PathBuf> {
-    match which(binary_name) {
-        Ok(v) => return Ok(v),
-        Err(_) => {}
+    if let Ok(v) = which(binary_name) {
+        return Ok(v);
     }
 
     let pwd = current_dir()?;
     for p in pwd.ancestors() {
-        match which(join!(p, "node_modules", ".bin", binary_name)) {
-            Ok(v) => return Ok(v),
-            Err(_) => {}
+        if let Ok(v) = which(join!(p, "node_modules", ".bin", binary_name)) {
+            return Ok(v);
         }
     }
 
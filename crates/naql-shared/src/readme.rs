@@ -0,0 +1,315 @@
+use crate::repository::Repository;
+use crate::vfs::Vfs;
+use crate::{join, ok, own};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use regex::{Captures, Regex};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs::create_dir_all;
+use std::ops::Range;
+use std::path::{Component, Path};
+use std::sync::LazyLock;
+use tracing::{debug, warn};
+
+/// Local images up to this size are inlined as data URIs, larger ones are
+/// bundled next to the README.
+const INLINE_LIMIT: u64 = 32 * 1024;
+
+/// Directory next to the README that large images are bundled in
+pub const IMAGES_DIR: &str = "readme";
+
+const MARKETPLACE: &str = "https://marketplace.visualstudio.com/items?itemName=";
+
+/// Badge image URLs from the VS Code marketplace and Open VSX
+const BADGES: [&str; 4] = [
+    "vsmarketplacebadge",
+    "/visual-studio-marketplace/",
+    "/vs-marketplace/",
+    "/open-vsx/",
+];
+
+/// Left in place of stripped markup, lines holding nothing else are dropped
+const REMOVED: char = '\0';
+
+static HTML_IMG: LazyLock<Regex> = LazyLock::new(|| ok!(Regex::new(r"(?i)<img\b[^>]*>")));
+static HTML_A: LazyLock<Regex> = LazyLock::new(|| ok!(Regex::new(r"(?i)<a\b[^>]*>")));
+static HTML_EMPTY_A: LazyLock<Regex> =
+    LazyLock::new(|| ok!(Regex::new(r"(?i)<a\b[^>]*>[\s\x00]*\x00[\s\x00]*</a>")));
+static HTML_ATTR: LazyLock<Regex> = LazyLock::new(|| {
+    ok!(Regex::new(
+        r#"(?i)\b(src|href)(\s*=\s*)(?:"([^"]*)"|'([^']*)')"#
+    ))
+});
+
+/// Rewrites a VS Code README for the Acode plugin page.
+pub struct Readme<'a> {
+    /// Extension the README is read from
+    pub vfs: &'a Vfs,
+    /// Directory relative links in the README resolve against
+    pub base: &'a Path,
+    /// Directory the rewritten README is written to
+    pub dest: &'a Path,
+    /// Repository of the extension
+    pub repository: Option<&'a Repository>,
+    /// `publisher.name` of the extension
+    pub origin: &'a str,
+}
+
+/// A link being parsed, with the images making up its text.
+struct Link {
+    range: Range<usize>,
+    dest: String,
+    images: usize,
+    badges: usize,
+}
+
+impl Readme<'_> {
+    /// Inlines or bundles local images, points relative links at the
+    /// repository, strips marketplace badges and prepends a note on the origin
+    /// of the port.
+    pub fn rewrite(&self, markdown: &str) -> String {
+        let mut edits: Vec<(Range<usize>, String)> = vec![];
+        let mut links: Vec<Link> = vec![];
+        let mut images = HashSet::new();
+
+        let options = Options::ENABLE_TABLES
+            | Options::ENABLE_FOOTNOTES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TASKLISTS
+            | Options::ENABLE_GFM;
+        let mut parser = Parser::new_ext(markdown, options).into_offset_iter();
+
+        for (event, range) in parser.by_ref() {
+            match event {
+                Event::Start(Tag::Link { dest_url, .. }) => links.push(Link {
+                    range,
+                    dest: dest_url.into_string(),
+                    images: 0,
+                    badges: 0,
+                }),
+                Event::End(TagEnd::Link) => {
+                    let link = ok!(links.pop());
+                    if link.images > 0 && (link.badges > 0 || is_marketplace(&link.dest)) {
+                        edits.push((link.range, REMOVED.to_string()));
+                    } else if let Some(url) = self.link(&link.dest) {
+                        edits.extend(find_dest(markdown, link.range, "](", &link.dest, url));
+                    }
+                }
+                Event::Start(Tag::Image { dest_url, .. }) => {
+                    let badge = is_badge(&dest_url);
+                    if let Some(link) = links.last_mut() {
+                        link.images += 1;
+                        link.badges += badge as usize;
+                    }
+
+                    if badge {
+                        edits.push((range, REMOVED.to_string()));
+                    } else if let Some(url) = self.image(&dest_url) {
+                        edits.extend(find_dest(markdown, range, "](", &dest_url, url));
+                        images.insert(dest_url.into_string());
+                    }
+                }
+                Event::Start(Tag::HtmlBlock) | Event::InlineHtml(_) => {
+                    let html = &markdown[range.clone()];
+                    let rewritten = self.html(html);
+                    if rewritten != html {
+                        edits.push((range, rewritten));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // reference-style links and images keep their url in the definition
+        for (_, def) in parser.reference_definitions().iter() {
+            let url = if images.contains(def.dest.as_ref()) {
+                self.image(&def.dest)
+            } else {
+                self.link(&def.dest)
+            };
+            if let Some(url) = url {
+                edits.extend(find_dest(markdown, def.span.clone(), "]:", &def.dest, url));
+            }
+        }
+
+        edits.sort_by_key(|(range, _)| (range.start, Reverse(range.end)));
+        let mut rewritten = String::with_capacity(markdown.len());
+        let mut cursor = 0;
+        for (range, text) in edits {
+            // nested in a removed link
+            if range.start < cursor {
+                continue;
+            }
+            rewritten.push_str(&markdown[cursor..range.start]);
+            rewritten.push_str(&text);
+            cursor = range.end;
+        }
+        rewritten.push_str(&markdown[cursor..]);
+
+        // inline HTML comes in one event per tag, so anchors are only known to
+        // be empty once all of their badges are removed
+        let rewritten = HTML_EMPTY_A.replace_all(&rewritten, REMOVED.to_string());
+
+        let body = rewritten
+            .split_inclusive('\n')
+            .filter(|line| {
+                !line.contains(REMOVED)
+                    || !line
+                        .trim_matches(|c: char| c == REMOVED || c.is_whitespace())
+                        .is_empty()
+            })
+            .collect::<String>()
+            .replace(REMOVED, "");
+
+        format!(
+            "> Ported with naql from [{origin}]({MARKETPLACE}{origin})\n\n{body}",
+            origin = self.origin
+        )
+    }
+
+    /// Rewrites `<img>` and `<a>` tags in raw HTML.
+    fn html(&self, html: &str) -> String {
+        let html = HTML_IMG.replace_all(html, |c: &Captures| {
+            let badge = HTML_ATTR
+                .captures_iter(&c[0])
+                .any(|attr| attr[1].eq_ignore_ascii_case("src") && is_badge(attr_value(&attr)));
+            if badge {
+                REMOVED.to_string()
+            } else {
+                self.html_attrs(&c[0])
+            }
+        });
+        HTML_A
+            .replace_all(&html, |c: &Captures| self.html_attrs(&c[0]))
+            .into_owned()
+    }
+
+    fn html_attrs(&self, tag: &str) -> String {
+        HTML_ATTR
+            .replace_all(tag, |c: &Captures| {
+                let value = attr_value(c);
+                let url = if c[1].eq_ignore_ascii_case("src") {
+                    self.image(value)
+                } else {
+                    self.link(value)
+                };
+
+                match url {
+                    Some(url) => format!("{}{}\"{url}\"", &c[1], &c[2]),
+                    None => own!(&c[0]),
+                }
+            })
+            .into_owned()
+    }
+
+    /// Relative link pointed at the repository.
+    fn link(&self, url: &str) -> Option<String> {
+        let (path, suffix) = relative(url)?;
+        let Some(repository) = self.repository else {
+            debug!("readme: no repository to resolve {url} against");
+            return None;
+        };
+
+        Some(format!("{}{suffix}", repository.file_url(&path)))
+    }
+
+    /// Local image inlined as a data URI, or bundled if it is large.
+    fn image(&self, url: &str) -> Option<String> {
+        let (path, _) = relative(url)?;
+        let src = join!(self.base, &path);
+        let Ok(len) = self.vfs.len(&src) else {
+            warn!("readme: image {url} does not exist");
+            return None;
+        };
+
+        if let Some(mime) = mime(&src)
+            && len <= INLINE_LIMIT
+        {
+            let data = self.vfs.read(&src).ok()?;
+            return Some(format!("data:{mime};base64,{}", STANDARD.encode(data)));
+        }
+
+        let dest = join!(self.dest, IMAGES_DIR, &path);
+        let bundled = create_dir_all(ok!(dest.parent()))
+            .map_err(anyhow::Error::from)
+            .and_then(|_| self.vfs.copy(&src, &dest));
+        if let Err(e) = bundled {
+            warn!("readme: cannot bundle image {url}: {e}");
+            return None;
+        }
+
+        Some(format!("{IMAGES_DIR}/{path}"))
+    }
+}
+
+/// Edit replacing `dest` in the source of a link or definition, `dest` follows
+/// the last `sep` of the span.
+fn find_dest(
+    markdown: &str,
+    span: Range<usize>,
+    sep: &str,
+    dest: &str,
+    url: String,
+) -> Option<(Range<usize>, String)> {
+    let text = &markdown[span.clone()];
+    let after = text.rfind(sep)? + sep.len();
+    let start = span.start + after + text[after..].find(dest)?;
+
+    Some((start..start + dest.len(), url))
+}
+
+/// Splits a relative url into its normalized path and its query or fragment,
+/// `None` for absolute urls, anchors and paths outside the extension.
+fn relative(url: &str) -> Option<(String, &str)> {
+    let scheme = url
+        .split_once(':')
+        .is_some_and(|(s, _)| !s.is_empty() && !s.contains(['/', '?', '#']));
+    if url.is_empty() || url.starts_with('#') || url.starts_with("//") || scheme {
+        return None;
+    }
+
+    let end = url.find(['?', '#']).unwrap_or(url.len());
+    let (path, suffix) = url.split_at(end);
+
+    let mut segments = vec![];
+    for component in Path::new(path.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(s) => segments.push(s.to_string_lossy()),
+            Component::ParentDir => {
+                segments.pop()?;
+            }
+            _ => {}
+        }
+    }
+
+    (!segments.is_empty()).then(|| (segments.join("/"), suffix))
+}
+
+fn attr_value<'c>(attr: &'c Captures) -> &'c str {
+    attr.get(3)
+        .or_else(|| attr.get(4))
+        .map_or("", |m| m.as_str())
+}
+
+fn mime(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    Some(match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "ico" => "image/x-icon",
+        _ => return None,
+    })
+}
+
+fn is_badge(url: &str) -> bool {
+    BADGES.iter().any(|b| url.contains(b))
+}
+
+fn is_marketplace(url: &str) -> bool {
+    url.contains("marketplace.visualstudio.com") || url.contains("open-vsx.org")
+}
@@ -0,0 +1,261 @@
+use crate::own;
+use std::str::FromStr;
+
+/// A hosted git repository, parsed from any of the forms npm accepts in the
+/// `repository` field of `package.json`:
+///
+/// - `github:user/repo`, `gitlab:user/repo`, `bitbucket:user/repo`
+/// - `user/repo`, shorthand for GitHub
+/// - `https://`, `git://`, `git+https://`, `git+ssh://` and `ssh://` URLs
+/// - `git@host:user/repo.git`
+///
+/// each optionally followed by a `#committish`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Repository {
+    /// Host name, e.g. `github.com`
+    pub host: String,
+    /// Path of the repository on the host, e.g. `user/repo`, or
+    /// `group/subgroup/repo` on GitLab
+    pub path: String,
+    /// Subdirectory of the repository holding the extension
+    pub directory: Option<String>,
+}
+
+impl Repository {
+    /// URL of the repository in a browser.
+    pub fn web_url(&self) -> String {
+        format!("https://{}/{}", self.host, self.path)
+    }
+
+    /// URL of the file at `path`, relative to [`Self::directory`], in a
+    /// browser.
+    pub fn file_url(&self, path: &str) -> String {
+        let blob = match self.host.as_str() {
+            host if is_gitlab(host) => "-/blob",
+            "bitbucket.org" => "src",
+            _ => "blob",
+        };
+
+        match &self.directory {
+            Some(directory) => format!("{}/{blob}/HEAD/{directory}/{path}", self.web_url()),
+            None => format!("{}/{blob}/HEAD/{path}", self.web_url()),
+        }
+    }
+
+    /// Owner of the repository if it is on GitHub.
+    pub fn github(&self) -> Option<&str> {
+        (self.host == "github.com").then(|| self.owner())
+    }
+
+    pub fn owner(&self) -> &str {
+        self.path.split('/').next().unwrap_or(&self.path)
+    }
+
+    pub fn with_directory(mut self, directory: Option<&str>) -> Self {
+        let directory = directory
+            .map(|d| d.trim_matches('/'))
+            .filter(|d| !d.is_empty());
+        if let Some(directory) = directory {
+            self.directory = Some(own!(directory));
+        }
+        self
+    }
+}
+
+impl FromStr for Repository {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("`{s}` is not a repository");
+
+        let shorthand = |host: &str, path: &str| Self::new(host, path).ok_or_else(invalid);
+
+        if let Some(path) = s.strip_prefix("github:") {
+            return shorthand("github.com", path);
+        } else if let Some(path) = s.strip_prefix("gitlab:") {
+            return shorthand("gitlab.com", path);
+        } else if let Some(path) = s.strip_prefix("bitbucket:") {
+            return shorthand("bitbucket.org", path);
+        }
+
+        let url = s.strip_prefix("git+").unwrap_or(s);
+        if let Some((_, rest)) = url.split_once("://") {
+            // drop credentials and port
+            let (authority, path) = rest.split_once('/').ok_or_else(invalid)?;
+            let host = authority.rsplit('@').next().unwrap_or(authority);
+            let host = host.split(':').next().unwrap_or(host);
+            return Self::from_url(host, path).ok_or_else(invalid);
+        }
+
+        // scp-like syntax
+        if let Some((user_host, path)) = s.split_once(':')
+            && let Some((_, host)) = user_host.split_once('@')
+        {
+            return Self::new(host, path).ok_or_else(invalid);
+        }
+
+        if !s.contains(':') && s.matches('/').count() == 1 {
+            return shorthand("github.com", s);
+        }
+
+        Err(invalid())
+    }
+}
+
+impl Repository {
+    fn new(host: &str, path: &str) -> Option<Self> {
+        let host = host.to_ascii_lowercase();
+        // `#committish` selects a ref, not a part of the repository
+        let path = path.split('#').next().unwrap_or(path);
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+
+        // GitLab nests projects in subgroups, its own routes start at `-`
+        let len = if is_gitlab(&host) {
+            segments
+                .iter()
+                .position(|s| *s == "-")
+                .unwrap_or(segments.len())
+        } else {
+            2
+        };
+        let segments = segments.get(..len)?;
+        if host.is_empty() || len < 2 || segments.iter().any(|s| s.is_empty()) {
+            return None;
+        }
+
+        let path = segments.join("/");
+        let path = path.strip_suffix(".git").unwrap_or(&path);
+        Some(Self {
+            host,
+            path: own!(path),
+            directory: None,
+        })
+    }
+
+    /// Like [`Self::new`], keeping the directory of browser URLs such as
+    /// `https://github.com/user/repo/tree/main/dir`.
+    fn from_url(host: &str, path: &str) -> Option<Self> {
+        let path = path.split(['?', '#']).next().unwrap_or(path);
+        let repository = Self::new(host, path)?;
+
+        let segments = path
+            .trim_matches('/')
+            .split('/')
+            .skip(repository.path.split('/').count())
+            .collect::<Vec<_>>();
+        let directory = match segments.as_slice() {
+            ["tree" | "blob" | "src", _, dir @ ..] | ["-", "tree" | "blob", _, dir @ ..] => {
+                dir.join("/")
+            }
+            _ => String::new(),
+        };
+
+        Some(repository.with_directory(Some(&directory)))
+    }
+}
+
+/// Whether `host` is GitLab, either gitlab.com or a self-hosted instance.
+fn is_gitlab(host: &str) -> bool {
+    host == "gitlab.com" || host.starts_with("gitlab.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> (String, String, Option<String>) {
+        let repository = s.parse::<Repository>().unwrap();
+        (repository.host, repository.path, repository.directory)
+    }
+
+    fn repo(host: &str, path: &str) -> (String, String, Option<String>) {
+        (own!(host), own!(path), None)
+    }
+
+    #[test]
+    fn shorthands() {
+        assert_eq!(parse("github:user/repo"), repo("github.com", "user/repo"));
+        assert_eq!(parse("gitlab:user/repo"), repo("gitlab.com", "user/repo"));
+        assert_eq!(
+            parse("bitbucket:user/repo"),
+            repo("bitbucket.org", "user/repo")
+        );
+        assert_eq!(parse("user/repo"), repo("github.com", "user/repo"));
+        assert!("repo".parse::<Repository>().is_err());
+        assert!("github:user".parse::<Repository>().is_err());
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(
+            parse("git+https://github.com/user/repo.git"),
+            repo("github.com", "user/repo")
+        );
+        assert_eq!(
+            parse("git+ssh://git@github.com:22/user/repo.git"),
+            repo("github.com", "user/repo")
+        );
+        assert_eq!(
+            parse("https://GitHub.com/user/repo/tree/main/packages/ext"),
+            (
+                own!("github.com"),
+                own!("user/repo"),
+                Some(own!("packages/ext"))
+            )
+        );
+    }
+
+    #[test]
+    fn scp() {
+        assert_eq!(
+            parse("git@github.com:user/repo.git"),
+            repo("github.com", "user/repo")
+        );
+        assert_eq!(
+            parse("git@gitlab.example.com:group/repo.git"),
+            repo("gitlab.example.com", "group/repo")
+        );
+    }
+
+    #[test]
+    fn gitlab_subgroups() {
+        assert_eq!(
+            parse("gitlab:group/sub/repo"),
+            repo("gitlab.com", "group/sub/repo")
+        );
+        assert_eq!(
+            parse("git@gitlab.com:group/sub/repo.git"),
+            repo("gitlab.com", "group/sub/repo")
+        );
+        let repository = "https://gitlab.com/group/sub/repo/-/tree/main/ext"
+            .parse::<Repository>()
+            .unwrap();
+        assert_eq!(repository.path, "group/sub/repo");
+        assert_eq!(
+            repository.file_url("a.png"),
+            "https://gitlab.com/group/sub/repo/-/blob/HEAD/ext/a.png"
+        );
+    }
+
+    #[test]
+    fn committish() {
+        assert_eq!(
+            parse("github:user/repo#main"),
+            repo("github.com", "user/repo")
+        );
+        assert_eq!(parse("user/repo#v1.0.0"), repo("github.com", "user/repo"));
+        assert_eq!(
+            parse("git@github.com:user/repo.git#main"),
+            repo("github.com", "user/repo")
+        );
+        assert_eq!(
+            parse("git+https://gitlab.com/group/sub/repo.git#semver:^1"),
+            repo("gitlab.com", "group/sub/repo")
+        );
+
+        let repository = "github:user/repo#main".parse::<Repository>().unwrap();
+        assert_eq!(repository.github(), Some("user"));
+        assert_eq!(repository.web_url(), "https://github.com/user/repo");
+    }
+}
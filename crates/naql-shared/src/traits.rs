@@ -1,3 +1,4 @@
+use crate::vfs::Vfs;
 use json_strip_comments::StripComments;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
@@ -12,13 +13,20 @@ pub trait ReadFromFile: DeserializeOwned {
 
         Ok(parsed)
     }
+
+    fn read_from_vfs<P: AsRef<Path>>(vfs: &Vfs, path: P) -> anyhow::Result<Self> {
+        let data = vfs.read(path)?;
+        let parsed = from_reader(StripComments::new(data.as_slice()))?;
+
+        Ok(parsed)
+    }
 }
 
 pub trait WriteToFile: Serialize {
     fn write_to_file<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
         let s = serde_json::to_string(self)?;
         let mut f = BufWriter::new(File::create(path)?);
-        f.write(s.as_bytes())?;
+        f.write_all(s.as_bytes())?;
         Ok(())
     }
 }
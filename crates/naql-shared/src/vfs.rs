@@ -0,0 +1,283 @@
+use crate::join;
+use crate::zip::{MAX_ENTRIES, MAX_SIZE};
+use anyhow::{Context, Result, anyhow, bail};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::FileExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use zip::ZipArchive;
+
+/// Read-only view of an extension, backed by its directory or straight by
+/// its .vsix. Relative paths resolve against the root of the extension and may
+/// use `..` to reach its parent, but not to leave the archive. Paths taken
+/// from the extension are untrusted, so absolute paths only reach the real
+/// filesystem under what was [trusted](Vfs::trust).
+#[derive(Clone)]
+pub struct Vfs {
+    source: Source,
+    /// Canonical real files and directories absolute paths may point into
+    trusted: Arc<Vec<PathBuf>>,
+}
+
+#[derive(Clone)]
+enum Source {
+    Dir(PathBuf),
+    Zip(Arc<ZipFs>),
+}
+
+pub struct ZipFs {
+    path: PathBuf,
+    /// Directory of the archive holding the extension
+    root: PathBuf,
+    /// Cloned for every read, so that threads don't wait on each other
+    archive: ZipArchive<FileAt>,
+    /// Bytes decompressed so far, bounded by [`MAX_SIZE`]
+    read: AtomicU64,
+}
+
+impl Vfs {
+    pub fn dir<P: AsRef<Path>>(root: P) -> Self {
+        Self::new(Source::Dir(root.as_ref().to_path_buf()))
+    }
+
+    /// Opens the zip file at `path`, with the extension under `root`.
+    pub fn zip<P: AsRef<Path>>(path: P, root: &str) -> Result<Self> {
+        let path = path.as_ref();
+        let archive = ZipArchive::new(FileAt::new(File::open(path)?))
+            .with_context(|| format!("cannot read {}", path.to_string_lossy()))?;
+        if archive.len() > MAX_ENTRIES {
+            bail!(
+                "{}: more than {MAX_ENTRIES} entries",
+                path.to_string_lossy()
+            );
+        }
+
+        Ok(Self::new(Source::Zip(Arc::new(ZipFs {
+            path: path.to_path_buf(),
+            root: PathBuf::from(root),
+            archive,
+            read: AtomicU64::new(0),
+        }))))
+    }
+
+    fn new(source: Source) -> Self {
+        Self {
+            source,
+            trusted: Arc::default(),
+        }
+    }
+
+    /// Lets absolute paths reach the real file or directory `path`, for what
+    /// the user points naql at rather than the extension, such as an override
+    /// manifest.
+    pub fn trust<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("cannot read {}", path.to_string_lossy()))?;
+        Arc::make_mut(&mut self.trusted).push(path);
+        Ok(())
+    }
+
+    /// Directory or archive the extension is read from.
+    pub fn location(&self) -> &Path {
+        match &self.source {
+            Source::Dir(root) => root,
+            Source::Zip(zip) => &zip.path,
+        }
+    }
+
+    pub fn is_file<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+        match self.backing(path) {
+            Backing::Real(path) => path.is_file(),
+            Backing::Zip(zip, name) => zip.index(&name).is_some(),
+            Backing::None => false,
+        }
+    }
+
+    /// Size of the file at `path` once decompressed.
+    pub fn len<P: AsRef<Path>>(&self, path: P) -> Result<u64> {
+        let path = path.as_ref();
+        match self.backing(path) {
+            Backing::Real(path) => Ok(fs::metadata(path)?.len()),
+            Backing::Zip(zip, name) => {
+                let index = zip.index(&name).ok_or_else(|| not_found(path))?;
+                Ok(zip.archive.clone().by_index(index)?.size())
+            }
+            Backing::None => Err(not_found(path)),
+        }
+    }
+
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>> {
+        let mut data = vec![];
+        self.stream(path.as_ref(), &mut data)?;
+        Ok(data)
+    }
+
+    pub fn read_to_string<P: AsRef<Path>>(&self, path: P) -> Result<String> {
+        let path = path.as_ref();
+        String::from_utf8(self.read(path)?)
+            .with_context(|| format!("{} is not UTF-8", path.to_string_lossy()))
+    }
+
+    /// Copies the file at `path` to `dest` on the real filesystem.
+    pub fn copy<P: AsRef<Path>, D: AsRef<Path>>(&self, path: P, dest: D) -> Result<u64> {
+        let mut dest = File::create(dest)?;
+        self.stream(path.as_ref(), &mut dest)
+    }
+
+    /// Files directly in the directory `path`.
+    pub fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<PathBuf>> {
+        let path = path.as_ref();
+        match self.backing(path) {
+            Backing::Real(dir) => Ok(fs::read_dir(dir)?
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().is_file())
+                .map(|e| join!(path, e.file_name()))
+                .collect()),
+            Backing::Zip(zip, dir) => {
+                let prefix = if dir.as_os_str().is_empty() {
+                    String::new()
+                } else {
+                    format!("{}/", dir.to_string_lossy())
+                };
+                Ok(zip
+                    .archive
+                    .file_names()
+                    .filter_map(|name| name.strip_prefix(&prefix))
+                    .filter(|name| !name.is_empty() && !name.contains('/'))
+                    .map(|name| join!(path, name))
+                    .collect())
+            }
+            Backing::None => Err(not_found(path)),
+        }
+    }
+
+    fn stream<W: io::Write>(&self, path: &Path, dest: &mut W) -> Result<u64> {
+        match self.backing(path) {
+            Backing::Real(path) => Ok(io::copy(
+                &mut File::open(&path)
+                    .map_err(|e| anyhow!("cannot read {}: {e}", path.to_string_lossy()))?,
+                dest,
+            )?),
+            Backing::Zip(zip, name) => zip.stream(&name, dest),
+            Backing::None => Err(not_found(path)),
+        }
+    }
+
+    fn backing(&self, path: &Path) -> Backing<'_> {
+        if path.is_absolute() {
+            // canonical, so that neither `..` nor symlinks leave what is trusted
+            return match path.canonicalize() {
+                Ok(path) if self.trusted.iter().any(|t| path.starts_with(t)) => Backing::Real(path),
+                _ => Backing::None,
+            };
+        }
+
+        match &self.source {
+            Source::Dir(root) => Backing::Real(join!(root, path)),
+            Source::Zip(zip) => match normalize(&join!(&zip.root, path)) {
+                Some(name) => Backing::Zip(zip, name),
+                None => Backing::None,
+            },
+        }
+    }
+}
+
+enum Backing<'a> {
+    Real(PathBuf),
+    /// Entry name in the archive
+    Zip(&'a ZipFs, PathBuf),
+    /// Outside the archive, or an untrusted absolute path
+    None,
+}
+
+impl ZipFs {
+    fn index(&self, name: &Path) -> Option<usize> {
+        self.archive.index_for_name(&name.to_string_lossy())
+    }
+
+    fn stream<W: io::Write>(&self, name: &Path, dest: &mut W) -> Result<u64> {
+        let index = self.index(name).ok_or_else(|| not_found(name))?;
+        let mut archive = self.archive.clone();
+        let mut file = archive.by_index(index)?;
+        if file.is_symlink() {
+            bail!("{}: symlink {}", self.path.to_string_lossy(), file.name());
+        }
+
+        // sizes in the archive may lie, count what is decompressed
+        let remaining = MAX_SIZE.saturating_sub(self.read.load(Ordering::Relaxed));
+        let written = io::copy(&mut (&mut file).take(remaining + 1), dest)?;
+        if self.read.fetch_add(written, Ordering::Relaxed) + written > MAX_SIZE {
+            bail!(
+                "{}: more than {MAX_SIZE} bytes uncompressed",
+                self.path.to_string_lossy()
+            );
+        }
+
+        Ok(written)
+    }
+}
+
+/// A shared file read at its own position, cheap to clone.
+#[derive(Clone)]
+struct FileAt {
+    file: Arc<File>,
+    pos: u64,
+}
+
+impl FileAt {
+    fn new(file: File) -> Self {
+        Self {
+            file: Arc::new(file),
+            pos: 0,
+        }
+    }
+}
+
+impl Read for FileAt {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.file.read_at(buf, self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for FileAt {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.file.metadata()?.len().checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        self.pos = pos
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?;
+        Ok(self.pos)
+    }
+}
+
+/// Resolves `.` and `..` in the relative `path`, `None` if it leaves the root.
+pub fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(s) => normalized.push(s),
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::CurDir => {}
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(normalized)
+}
+
+fn not_found(path: &Path) -> anyhow::Error {
+    anyhow!("{} does not exist", path.to_string_lossy())
+}
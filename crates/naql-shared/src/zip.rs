@@ -1,92 +1,228 @@
-use crate::{ok, own};
-use anyhow::Result;
+use crate::{join, ok, own};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
 use size::{Base, Size, Style};
+use std::collections::BTreeSet;
 use std::fs::{self, File};
-use std::io::{self, Read, Write};
+use std::io::{self, BufWriter, Read};
 use std::os::unix::fs::MetadataExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive};
 
+/// Most entries extracted from an archive
+pub const MAX_ENTRIES: usize = 200_000;
+
+/// Most bytes extracted from an archive
+pub const MAX_SIZE: u64 = 1 << 30;
+
+/// Extracts the untrusted archive `src`, such as a plugin zip, to `dest`.
+/// Entries escaping `dest` and symlinks are rejected, unix modes are reduced
+/// to `0o644` or `0o755`, and archives over [`MAX_ENTRIES`] or [`MAX_SIZE`]
+/// fail.
+///
+/// A .vsix isn't extracted at all: [`Vfs::zip`](crate::vfs::Vfs::zip) reads
+/// the members under its `extension/` directory lazily, when they are used,
+/// within the same limits.
 pub fn unzip<P1: AsRef<Path>, P2: AsRef<Path>>(src: P1, dest: P2) -> Result<()> {
-    let file = fs::File::open(src)?;
+    let src = src.as_ref();
+    let mut archive = zip::ZipArchive::new(File::open(src)?)
+        .with_context(|| format!("cannot read {}", src.to_string_lossy()))?;
 
-    let mut archive = zip::ZipArchive::new(file)?;
+    if archive.len() > MAX_ENTRIES {
+        bail!("{}: more than {MAX_ENTRIES} entries", src.to_string_lossy());
+    }
 
+    let mut total = 0;
     for i in 0..archive.len() {
-        let mut outpath = own!(dest.as_ref());
         let mut file = archive.by_index(i)?;
-        let out = match file.enclosed_name() {
-            Some(path) => path,
-            None => continue,
+        let Some(name) = file.enclosed_name() else {
+            bail!("{}: unsafe path {}", src.to_string_lossy(), file.name());
         };
-        outpath.push(out);
+        if file.is_symlink() {
+            bail!("{}: symlink {}", src.to_string_lossy(), file.name());
+        }
 
+        let outpath = join!(dest.as_ref(), name);
         if file.is_dir() {
             fs::create_dir_all(&outpath)?;
-        } else {
-            if let Some(p) = outpath.parent() {
-                if !p.exists() {
-                    fs::create_dir_all(p)?;
-                }
-            }
-            let mut outfile = fs::File::create(&outpath)?;
-            io::copy(&mut file, &mut outfile)?;
+            continue;
+        }
+
+        if let Some(p) = outpath.parent() {
+            fs::create_dir_all(p)?;
+        }
+
+        // sizes in the archive may lie, count what is written
+        let mut outfile = File::create(&outpath)?;
+        total += io::copy(&mut (&mut file).take(MAX_SIZE - total + 1), &mut outfile)?;
+        if total > MAX_SIZE {
+            bail!(
+                "{}: more than {} uncompressed",
+                src.to_string_lossy(),
+                format_size(MAX_SIZE)
+            );
         }
 
-        // Get and Set permissions
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
 
             if let Some(mode) = file.unix_mode() {
+                let mode = if mode & 0o111 == 0 { 0o644 } else { 0o755 };
                 fs::set_permissions(&outpath, fs::Permissions::from_mode(mode))?;
             }
         }
     }
+
     Ok(())
 }
 
-pub fn zip<P1: AsRef<Path>, P2: AsRef<Path>>(src: P1, dest: P2) -> Result<String> {
-    let file = File::create(dest)?;
+/// Extensions of formats that are already compressed, stored as is
+const COMPRESSED: [&str; 12] = [
+    "png", "jpg", "jpeg", "gif", "webp", "woff", "woff2", "zip", "gz", "br", "mp3", "mp4",
+];
+
+#[derive(Clone, Copy, Default)]
+pub enum Method {
+    #[default]
+    Deflate,
+    /// Smaller and faster than deflate, but not supported by Acode
+    Zstd,
+    Store,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct ZipOptions {
+    pub method: Method,
+    /// Compression level, the method's default if `None`
+    pub level: Option<i64>,
+}
+
+impl ZipOptions {
+    /// Checks `level` against the range supported by `method`.
+    pub fn new(method: Method, level: Option<i64>) -> Result<Self> {
+        let range = match method {
+            // 10 and above use zopfli
+            Method::Deflate => 1..=264,
+            Method::Zstd => -7..=22,
+            Method::Store => 0..=0,
+        };
+        if let Some(level) = level
+            && !range.contains(&level)
+        {
+            bail!(
+                "compression level {level} is outside {}..={}",
+                range.start(),
+                range.end()
+            );
+        }
+
+        Ok(Self { method, level })
+    }
+
+    fn for_file(&self, path: &Path) -> SimpleFileOptions {
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        let compressed = ext.is_some_and(|e| COMPRESSED.contains(&e.as_str()));
 
-    let walkdir = WalkDir::new(&src);
-    let dir_entries = walkdir.into_iter();
-    let dir_entries = dir_entries.filter_map(|e| e.ok());
+        let method = match self.method {
+            _ if compressed => CompressionMethod::Stored,
+            Method::Deflate => CompressionMethod::Deflated,
+            Method::Zstd => CompressionMethod::Zstd,
+            Method::Store => CompressionMethod::Stored,
+        };
+        let level = if method == CompressionMethod::Stored {
+            None
+        } else {
+            self.level
+        };
+
+        SimpleFileOptions::default()
+            .compression_method(method)
+            .compression_level(level)
+    }
+}
 
-    let mut zip = zip::ZipWriter::new(file);
-    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+/// File written to a zip, with its size before and after compression.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ZipEntry {
+    pub name: String,
+    pub raw: u64,
+    pub compressed: u64,
+}
+
+/// What [`zip`] wrote.
+pub struct ZipReport {
+    /// Size of the zip file
+    pub size: u64,
+    pub entries: Vec<ZipEntry>,
+}
 
+/// Writes `entries` of `src`, relative paths of files or directories, to
+/// the zip file `dest`.
+pub fn zip<P1: AsRef<Path>, P2: AsRef<Path>>(
+    src: P1,
+    dest: P2,
+    entries: &[PathBuf],
+    options: ZipOptions,
+) -> Result<ZipReport> {
+    let dest = dest.as_ref();
+    let file = File::create(dest)?;
     let prefix = src.as_ref();
-    let mut buffer = Vec::new();
 
-    for entry in dir_entries {
-        let path = entry.path();
+    let mut paths = BTreeSet::new();
+    for entry in entries {
+        let dir_entries = WalkDir::new(join!(prefix, entry)).into_iter();
+        for entry in dir_entries.filter_map(|e| e.ok()) {
+            // parent directories are written explicitly, as some unzip tools
+            // don't create them from file paths
+            for ancestor in entry.path().ancestors() {
+                if ancestor == prefix || !paths.insert(own!(ancestor)) {
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut zip = zip::ZipWriter::new(BufWriter::new(file));
+
+    for path in paths {
         let name = ok!(path.strip_prefix(prefix));
         let path_as_string = name.to_string_lossy();
 
-        // Write file or directory explicitly
-        // Some unzip tools unzip files with directory paths correctly, some do not!
         if path.is_file() {
-            zip.start_file(path_as_string, options)?;
-            let mut f = File::open(path)?;
-
-            f.read_to_end(&mut buffer)?;
-            zip.write_all(&buffer)?;
-            buffer.clear();
-        } else if !name.as_os_str().is_empty() {
-            // Only if not root! Avoids path spec / warning
-            // and mapname conversion failed error on unzip
-            zip.add_directory(path_as_string, options)?;
+            zip.start_file(path_as_string, options.for_file(&path))?;
+            io::copy(&mut File::open(&path)?, &mut zip)?;
+        } else {
+            zip.add_directory(path_as_string, SimpleFileOptions::default())?;
+        }
+    }
+    let size = zip.finish()?.into_inner()?.metadata()?.size();
+
+    // compressed sizes are only known once written
+    let mut archive = ZipArchive::new(File::open(dest)?)?;
+    let mut entries = vec![];
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        if file.is_file() {
+            entries.push(ZipEntry {
+                name: own!(file.name()),
+                raw: file.size(),
+                compressed: file.compressed_size(),
+            });
         }
     }
 
-    let size = zip.finish()?.metadata()?.size();
-    let size = Size::from_bytes(size)
+    Ok(ZipReport { size, entries })
+}
+
+pub fn format_size(bytes: u64) -> String {
+    Size::from_bytes(bytes)
         .format()
         .with_base(Base::Base10)
         .with_style(Style::AbbreviatedLowercase)
-        .to_string();
-
-    Ok(size)
+        .to_string()
 }
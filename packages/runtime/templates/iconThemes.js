@@ -1,5 +1,6 @@
 const Url = acode.require("url");
 const details = {{ details }};
+const firstInstall = {{ first_install }};
 
 export default {
   init(firstInit, baseUrl) {
@@ -15,12 +16,14 @@ export default {
       });
     }
 
-    if (firstInit) {
+    if (firstInit && firstInstall.mode === "picker") {
       editorManager.editor.commands.exec(
         "Preferences: File Icon Theme",
         editorManager.editor,
         undefined
       );
+    } else if (firstInit && firstInstall.mode === "activate") {
+      window.vsApi.setIconTheme(firstInstall.id);
     }
   },
 